@@ -0,0 +1,50 @@
+//! Простой имитатор rotctld для проверки слежения без настоящего ротатора.
+//! Запуск: cargo run --example mock_rotctld -- 127.0.0.1:4533
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let address = std::env::args().nth(1).unwrap_or("127.0.0.1:4533".to_string());
+    let listener = TcpListener::bind(&address).await?;
+    println!("Mock rotctld is listening on {}", address);
+
+    loop {
+        let (socket, peer) = listener.accept().await?;
+        println!("Client {} connected", peer);
+
+        tokio::spawn(async move {
+            let (reader, mut writer) = socket.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let (mut azimuth, mut elevation) = (0.0_f64, 0.0_f64);
+
+            while let Ok(Some(line)) = lines.next_line().await {
+                let parts: Vec<&str> = line.split_whitespace().collect();
+
+                let answer = match parts.as_slice() {
+                    ["P", az, el] => match (az.parse(), el.parse()) {
+                        (Ok(az), Ok(el)) => {
+                            (azimuth, elevation) = (az, el);
+                            println!("P {:.2} {:.2}", azimuth, elevation);
+                            "RPRT 0\n".to_string()
+                        }
+                        _ => "RPRT -1\n".to_string(),
+                    },
+                    ["p"] => format!("{:.6}\n{:.6}\n", azimuth, elevation),
+                    ["S"] => {
+                        println!("S");
+                        "RPRT 0\n".to_string()
+                    }
+                    _ => "RPRT -4\n".to_string(),
+                };
+
+                if writer.write_all(answer.as_bytes()).await.is_err() {
+                    break;
+                }
+            }
+
+            println!("Client {} disconnected", peer);
+        });
+    }
+}
@@ -1,14 +1,13 @@
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
 use roots::{find_root_brent, SimpleConvergency};
 use serde::Serialize;
 use thiserror::Error;
 
+use super::horizon_mask::HorizonMask;
+use super::magnitudes::StandardMagnitudes;
+
 #[derive(Debug, Error)]
 pub enum PassesCalculationError {
-    #[error("Failed to open tle at the specified path")]
-    TleLoadingFailed(#[from] std::io::Error),
-    #[error("Failed to find the specified satellite at the specified tle path")]
-    SatelliteNotFound,
     #[error("Failed to find function root")]
     RootCalculationError(#[from] roots::SearchError),
     #[error("Failed to propogate satellite")]
@@ -18,6 +17,7 @@ pub enum PassesCalculationError {
 #[derive(Debug, Serialize)]
 pub struct PassData {
     pub satellite_name: String,
+    pub norad_id: String,
     pub rise_time: DateTime<Utc>,
     pub rise_azimuth: f64,
     pub apogee_time: DateTime<Utc>,
@@ -25,53 +25,120 @@ pub struct PassData {
     pub apogee_azimuth: f64,
     pub fall_time: DateTime<Utc>,
     pub fall_azimuth: f64,
+    pub illumination: Illumination,
+    pub observer_lighting: ObserverLighting,
+    pub visible_intervals: Vec<VisibleInterval>,
+    pub apogee_magnitude: Option<f64>,
 }
 
-pub fn find_satrec(
-    tle_file_path: &str, satellite_name: &str,
-) -> Result<satellite::io::Satrec, PassesCalculationError> {
-    let tle = std::fs::read_to_string(tle_file_path)?;
-    let (satrecs, _errors) = satellite::io::parse_multiple(&tle);
-
-    for possible_satrec in satrecs {
-        if let Some(possible_name) = &possible_satrec.name {
-            if possible_name == satellite_name {
-                return Ok(possible_satrec);
-            }
-        }
-    }
-
-    Err(PassesCalculationError::SatelliteNotFound)
+#[derive(Debug)]
+pub struct LookSample {
+    pub azimuth: f64,
+    pub elevation: f64,
+    pub range: f64,
+    pub range_rate: f64,
+    pub magnitude: Option<f64>,
 }
 
-fn get_elevation_safe(
+/// Азимут и угол места в радианах
+fn get_look_angles(
     satrec: &satellite::io::Satrec,
     observer: &satellite::Geodedic,
     time: DateTime<Utc>,
-) -> f64 {
+) -> Result<satellite::Bearing, PassesCalculationError> {
     let propogation = match satellite::propogation::propogate_datetime(satrec, time) {
         Ok(propogation) => propogation,
-        Err(_) => return f64::NAN
+        Err(_) => return Err(PassesCalculationError::PropogationError),
     };
     let gmst = satellite::propogation::gstime::gstime_datetime(time);
     let position_ecf = satellite::transforms::eci_to_ecf(&propogation.position, gmst);
-    let look_angles = satellite::transforms::ecf_to_look_angles(observer, &position_ecf);
-    look_angles.elevation
+
+    Ok(satellite::transforms::ecf_to_look_angles(observer, &position_ecf))
 }
 
-fn get_observer_look(
+fn get_elevation_safe(
     satrec: &satellite::io::Satrec,
+    observer: &satellite::Geodedic,
     time: DateTime<Utc>,
+) -> f64 {
+    get_look_angles(satrec, observer, time).map_or(f64::NAN, |look_angles| look_angles.elevation)
+}
+
+/// Условия у наблюдателя для поправки на атмосферную рефракцию
+#[derive(Debug, Clone, Copy)]
+pub struct Refraction {
+    pub pressure_hpa: f64,
+    pub temperature_celsius: f64,
+}
+
+impl Refraction {
+    /// Стандартная атмосфера, для которой выведена формула Сэмундссона
+    const STANDARD_PRESSURE_HPA: f64 = 1010.0;
+    const STANDARD_TEMPERATURE_CELSIUS: f64 = 10.0;
+    /// Ниже этого угла места формула неприменима, поэтому поправка дальше не растет
+    const MIN_ELEVATION: f64 = -1.0;
+
+    /// Не указанные давление и температура берутся из стандартной атмосферы
+    pub fn from_conditions(pressure_hpa: Option<f64>, temperature_celsius: Option<f64>) -> Self {
+        Refraction {
+            pressure_hpa: pressure_hpa.unwrap_or(Self::STANDARD_PRESSURE_HPA),
+            temperature_celsius: temperature_celsius.unwrap_or(Self::STANDARD_TEMPERATURE_CELSIUS),
+        }
+    }
+
+    /// Видимый угол места по геометрическому, оба в градусах
+    pub fn apply(&self, elevation: f64) -> f64 {
+        let clamped_elevation = elevation.max(Self::MIN_ELEVATION);
+        let standard_correction_arcmin = 1.02
+            / (clamped_elevation + 10.3 / (clamped_elevation + 5.11)).to_radians().tan();
+
+        let density_factor = self.pressure_hpa / Self::STANDARD_PRESSURE_HPA
+            * (273.15 + Self::STANDARD_TEMPERATURE_CELSIUS) / (273.15 + self.temperature_celsius);
+
+        elevation + standard_correction_arcmin * density_factor / 60.0
+    }
+}
+
+/// Видимый угол места в радианах; без модели рефракции возвращается геометрический
+fn apply_refraction(elevation: f64, refraction: Option<Refraction>) -> f64 {
+    match refraction {
+        Some(refraction) => refraction.apply(elevation * satellite::constants::RAD_TO_DEG)
+            * satellite::constants::DEG_2_RAD,
+        None => elevation,
+    }
+}
+
+/// Запас углом места (радианы) над маской горизонта и минимальным углом места, NaN при ошибке распространения
+fn get_mask_margin_safe(
+    satrec: &satellite::io::Satrec,
     observer: &satellite::Geodedic,
-) -> Result<satellite::Bearing, PassesCalculationError> {
-    let propogation = match satellite::propogation::propogate_datetime(satrec, time) {
-        Ok(propogation) => propogation,
-        Err(_) => return Err(PassesCalculationError::PropogationError),
+    time: DateTime<Utc>,
+    min_elevation: f64,
+    horizon_mask: Option<&HorizonMask>,
+    refraction: Option<Refraction>,
+) -> f64 {
+    let Ok(look_angles) = get_look_angles(satrec, observer, time) else {
+        return f64::NAN;
     };
 
-    let gmst = satellite::propogation::gstime::gstime_datetime(time);
-    let position_ecf = satellite::transforms::eci_to_ecf(&propogation.position, gmst);
-    let mut look_angles = satellite::transforms::ecf_to_look_angles(&observer, &position_ecf);
+    let threshold = match horizon_mask {
+        Some(horizon_mask) => f64::max(
+            min_elevation,
+            horizon_mask.min_elevation(look_angles.azimuth * satellite::constants::RAD_TO_DEG)
+                * satellite::constants::DEG_2_RAD,
+        ),
+        None => min_elevation,
+    };
+
+    apply_refraction(look_angles.elevation, refraction) - threshold
+}
+
+pub fn get_observer_look(
+    satrec: &satellite::io::Satrec,
+    time: DateTime<Utc>,
+    observer: &satellite::Geodedic,
+) -> Result<satellite::Bearing, PassesCalculationError> {
+    let mut look_angles = get_look_angles(satrec, observer, time)?;
 
     look_angles.elevation *= satellite::constants::RAD_TO_DEG;
     look_angles.azimuth *= satellite::constants::RAD_TO_DEG;
@@ -79,6 +146,91 @@ fn get_observer_look(
     Ok(look_angles)
 }
 
+/// Эпоха набора элементов; epochdays в tle отсчитывается от единицы, то есть 1.0 - полночь 1 января
+pub fn get_epoch(satrec: &satellite::io::Satrec) -> DateTime<Utc> {
+    get_epoch_from_tle(satrec.epochyr as i32, satrec.epochdays)
+}
+
+/// Эпоха по двузначному году и дню года из первой строки tle
+pub fn get_epoch_from_tle(epoch_year: i32, epoch_days: f64) -> DateTime<Utc> {
+    let year = if epoch_year < 57 { 2000 + epoch_year } else { 1900 + epoch_year };
+    let start_of_year = NaiveDate::from_ymd_opt(year, 1, 1)
+        .expect("January 1st of the epoch year should exist")
+        .and_hms_opt(0, 0, 0)
+        .expect("Midnight should exist")
+        .and_utc();
+
+    start_of_year + Duration::milliseconds(((epoch_days - 1.0) * 86_400_000.0).round() as i64)
+}
+
+/// Номер витка, на котором в момент time идет пролет над наблюдателем на широте observer_lat (градусы)
+pub fn get_revolution_number(
+    satrec: &satellite::io::Satrec,
+    revolution_at_epoch: u32,
+    time: DateTime<Utc>,
+    observer_lat: f64,
+) -> i64 {
+    let minutes_since_epoch = (time - get_epoch(satrec)).num_milliseconds() as f64 / 60_000.0;
+    let argument_of_latitude = (satrec.argpo + satrec.mo).rem_euclid(satellite::constants::TWO_PI)
+        + satrec.no * minutes_since_epoch;
+
+    count_pass_revolutions(revolution_at_epoch, argument_of_latitude, observer_lat)
+}
+
+/// Номер в tle меняется в восходящем узле, но у наблюдателя рядом с экватором кульминация может прийтись
+/// прямо на узел, и тогда сдвиг пролета на секунды менял бы номер. Поэтому витки здесь отсчитываются
+/// от самой дальней от наблюдателя по широте точки орбиты, где кульминаций не бывает.
+/// argument_of_latitude - средний аргумент широты в радианах, отсчитанный от узла витка на эпоху
+pub fn count_pass_revolutions(revolution_at_epoch: u32, argument_of_latitude: f64, observer_lat: f64) -> i64 {
+    let start_of_revolution = if observer_lat >= 0.0 {
+        1.5 * std::f64::consts::PI
+    } else {
+        0.5 * std::f64::consts::PI
+    };
+
+    revolution_at_epoch as i64 +
+        ((argument_of_latitude - start_of_revolution) / satellite::constants::TWO_PI).floor() as i64
+}
+
+/// Наборы элементов одного спутника с разными эпохами: положение на каждый момент
+/// считается по набору, эпоха которого к нему ближе всего
+pub struct ElementSets<'a> {
+    current: &'a satellite::io::Satrec,
+    /// Все наборы вместе с текущим, по возрастанию эпохи
+    by_epoch: Vec<(DateTime<Utc>, &'a satellite::io::Satrec)>,
+}
+
+impl<'a> ElementSets<'a> {
+    pub fn new(current: &'a satellite::io::Satrec, history: &'a [satellite::io::Satrec]) -> Self {
+        let mut by_epoch: Vec<_> = std::iter::once(current)
+            .chain(history)
+            .map(|satrec| (get_epoch(satrec), satrec))
+            .collect();
+        by_epoch.sort_by_key(|&(epoch, _)| epoch);
+
+        ElementSets { current, by_epoch }
+    }
+
+    pub fn single(satrec: &'a satellite::io::Satrec) -> Self {
+        ElementSets::new(satrec, &[])
+    }
+
+    /// Текущий набор из каталога, по нему определяются имя, номер и звездная величина спутника
+    pub fn current(&self) -> &'a satellite::io::Satrec {
+        self.current
+    }
+
+    pub fn at(&self, time: DateTime<Utc>) -> &'a satellite::io::Satrec {
+        let index = self.by_epoch.partition_point(|&(epoch, _)| epoch < time);
+
+        self.by_epoch[index.saturating_sub(1)..usize::min(index + 1, self.by_epoch.len())]
+            .iter()
+            .min_by_key(|&&(epoch, _)| (epoch - time).num_milliseconds().abs())
+            .map(|&(_, satrec)| satrec)
+            .unwrap_or(self.current)
+    }
+}
+
 pub fn get_satellite_pos(
     satrec: &satellite::io::Satrec,
     time: DateTime<Utc>,
@@ -97,7 +249,347 @@ pub fn get_satellite_pos(
     Ok(sat_pos)
 }
 
-fn get_max_parab<F>(mut fun: F, start: f64, end: f64, tol: f64) -> f64
+const AU_KM: f64 = 149_597_870.7;
+const SUN_RADIUS_KM: f64 = 696_000.0;
+pub const EARTH_RADIUS_KM: f64 = 6378.137;
+const EARTH_FLATTENING: f64 = 1.0 / 298.257_223_563;
+const EARTH_ROTATION_RAD_PER_SEC: f64 = 7.292_115e-5;
+const SPEED_OF_LIGHT_KM_PER_SEC: f64 = 299_792.458;
+
+/// Шаг, с которым ищутся смены видимости спутника во время пролета
+const VISIBILITY_STEP_SECONDS: f64 = 10.0;
+/// Пролеты не короче этой длительности гарантированно находятся поиском
+pub const MIN_DETECTABLE_PASS_SECONDS: f64 = 20.0;
+/// Даже для геостационарных спутников угол места проверяется хотя бы раз в час
+const MAX_SCAN_STEP_MINUTES: f64 = 60.0;
+/// Шаг, с которым внутри пролета ищутся кульминация и пересечение минимального угла места
+const PASS_SCAN_STEP_MINUTES: f64 = 1.0;
+const EARTH_GM_KM3_PER_SEC2: f64 = 398_600.441_8;
+/// Запас на торможение в атмосфере и неточность оценки скорости изменения угла места
+const ELEVATION_RATE_MARGIN: f64 = 1.5;
+/// Нижняя граница высоты перигея в оценке, чтобы она не вырождалась для сходящих с орбиты объектов
+const MIN_PERIGEE_ALTITUDE_KM: f64 = 100.0;
+/// Спутник можно увидеть глазом, только если Солнце у наблюдателя ниже этой высоты (конец гражданских сумерек)
+const MAX_SUN_ELEVATION_FOR_VISIBILITY: f64 = -6.0;
+
+/// Освещенность спутника по конической модели тени Земли
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Illumination {
+    Sunlit,
+    Penumbra,
+    Umbra,
+}
+
+/// Время суток у наблюдателя по высоте Солнца
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObserverLighting {
+    Daylight,
+    CivilTwilight,
+    NauticalTwilight,
+    AstronomicalTwilight,
+    Darkness,
+}
+
+impl ObserverLighting {
+    fn from_sun_elevation(sun_elevation: f64) -> Self {
+        match sun_elevation {
+            elevation if elevation > -0.833 => ObserverLighting::Daylight,
+            elevation if elevation > -6.0 => ObserverLighting::CivilTwilight,
+            elevation if elevation > -12.0 => ObserverLighting::NauticalTwilight,
+            elevation if elevation > -18.0 => ObserverLighting::AstronomicalTwilight,
+            _ => ObserverLighting::Darkness,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VisibleInterval {
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+}
+
+fn dot(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn norm(a: &[f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+fn sub(a: &[f64; 3], b: &[f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn julian_date(time: DateTime<Utc>) -> f64 {
+    time.timestamp_millis() as f64 / 86_400_000.0 + 2_440_587.5
+}
+
+fn eci_to_ecf(position: &[f64; 3], gmst: f64) -> [f64; 3] {
+    [
+        position[0] * gmst.cos() + position[1] * gmst.sin(),
+        -position[0] * gmst.sin() + position[1] * gmst.cos(),
+        position[2],
+    ]
+}
+
+fn ecf_to_eci(position: &[f64; 3], gmst: f64) -> [f64; 3] {
+    [
+        position[0] * gmst.cos() - position[1] * gmst.sin(),
+        position[0] * gmst.sin() + position[1] * gmst.cos(),
+        position[2],
+    ]
+}
+
+fn geodedic_to_ecf(observer: &satellite::Geodedic) -> [f64; 3] {
+    let e2 = EARTH_FLATTENING * (2.0 - EARTH_FLATTENING);
+    let normal = EARTH_RADIUS_KM / (1.0 - e2 * observer.latitude.sin().powi(2)).sqrt();
+
+    [
+        (normal + observer.height) * observer.latitude.cos() * observer.longitude.cos(),
+        (normal + observer.height) * observer.latitude.cos() * observer.longitude.sin(),
+        (normal * (1.0 - e2) + observer.height) * observer.latitude.sin(),
+    ]
+}
+
+/// Положение (км) и скорость (км/с) спутника в ECI
+fn get_state_eci(
+    satrec: &satellite::io::Satrec,
+    time: DateTime<Utc>,
+) -> Result<([f64; 3], [f64; 3]), PassesCalculationError> {
+    let propogation = match satellite::propogation::propogate_datetime(satrec, time) {
+        Ok(propogation) => propogation,
+        Err(_) => return Err(PassesCalculationError::PropogationError),
+    };
+
+    Ok((
+        [propogation.position.x, propogation.position.y, propogation.position.z],
+        [propogation.velocity.x, propogation.velocity.y, propogation.velocity.z],
+    ))
+}
+
+fn get_position_eci(
+    satrec: &satellite::io::Satrec,
+    time: DateTime<Utc>,
+) -> Result<[f64; 3], PassesCalculationError> {
+    Ok(get_state_eci(satrec, time)?.0)
+}
+
+/// Наклонная дальность (км) и скорость ее изменения (км/с, положительна при удалении спутника)
+pub fn get_range_and_range_rate(
+    satrec: &satellite::io::Satrec,
+    observer: &satellite::Geodedic,
+    time: DateTime<Utc>,
+) -> Result<(f64, f64), PassesCalculationError> {
+    let (position_eci, velocity_eci) = get_state_eci(satrec, time)?;
+
+    let gmst = satellite::propogation::gstime::gstime_datetime(time);
+    let observer_eci = ecf_to_eci(&geodedic_to_ecf(observer), gmst);
+    let observer_velocity_eci = [
+        -EARTH_ROTATION_RAD_PER_SEC * observer_eci[1],
+        EARTH_ROTATION_RAD_PER_SEC * observer_eci[0],
+        0.0,
+    ];
+
+    let relative_position = sub(&position_eci, &observer_eci);
+    let relative_velocity = sub(&velocity_eci, &observer_velocity_eci);
+    let range = norm(&relative_position);
+
+    Ok((range, dot(&relative_position, &relative_velocity) / range))
+}
+
+/// Частота, которую примет наземная станция от передатчика спутника на частоте frequency_hz
+pub fn get_downlink_frequency(frequency_hz: f64, range_rate: f64) -> f64 {
+    frequency_hz * SPEED_OF_LIGHT_KM_PER_SEC / (SPEED_OF_LIGHT_KM_PER_SEC + range_rate)
+}
+
+/// Частота, на которой нужно передавать, чтобы спутник принял сигнал на частоте frequency_hz
+pub fn get_uplink_frequency(frequency_hz: f64, range_rate: f64) -> f64 {
+    frequency_hz * (SPEED_OF_LIGHT_KM_PER_SEC + range_rate) / SPEED_OF_LIGHT_KM_PER_SEC
+}
+
+/// Положение Солнца в ECI (км) по упрощенной формуле Астрономического ежегодника, точность ~0.01°
+pub fn get_sun_position_eci(time: DateTime<Utc>) -> [f64; 3] {
+    let days = julian_date(time) - 2_451_545.0;
+
+    let mean_longitude = (280.460 + 0.985_647_4 * days) * satellite::constants::DEG_2_RAD;
+    let mean_anomaly = (357.528 + 0.985_600_3 * days) * satellite::constants::DEG_2_RAD;
+    let equation_of_center = 1.915 * mean_anomaly.sin() + 0.020 * (2.0 * mean_anomaly).sin();
+    let ecliptic_longitude = mean_longitude + equation_of_center * satellite::constants::DEG_2_RAD;
+    let obliquity = (23.439 - 0.000_000_4 * days) * satellite::constants::DEG_2_RAD;
+    let distance =
+        (1.000_14 - 0.016_71 * mean_anomaly.cos() - 0.000_14 * (2.0 * mean_anomaly).cos()) * AU_KM;
+
+    [
+        distance * ecliptic_longitude.cos(),
+        distance * obliquity.cos() * ecliptic_longitude.sin(),
+        distance * obliquity.sin() * ecliptic_longitude.sin(),
+    ]
+}
+
+/// Высота Солнца над горизонтом наблюдателя в градусах
+pub fn get_sun_elevation(observer: &satellite::Geodedic, time: DateTime<Utc>) -> f64 {
+    let gmst = satellite::propogation::gstime::gstime_datetime(time);
+    let sun_ecf = eci_to_ecf(&get_sun_position_eci(time), gmst);
+    let observer_ecf = geodedic_to_ecf(observer);
+
+    let up = [
+        observer.latitude.cos() * observer.longitude.cos(),
+        observer.latitude.cos() * observer.longitude.sin(),
+        observer.latitude.sin(),
+    ];
+    let to_sun = sub(&sun_ecf, &observer_ecf);
+
+    (dot(&to_sun, &up) / norm(&to_sun)).asin() * satellite::constants::RAD_TO_DEG
+}
+
+/// Угловые размеры Земли и Солнца, видимые со спутника, и угол между ними (радианы)
+fn get_shadow_geometry(position_eci: &[f64; 3], sun_eci: &[f64; 3]) -> (f64, f64, f64) {
+    let to_sun = sub(sun_eci, position_eci);
+    let to_earth = [-position_eci[0], -position_eci[1], -position_eci[2]];
+
+    let earth_semidiameter = (EARTH_RADIUS_KM / norm(position_eci)).min(1.0).asin();
+    let sun_semidiameter = (SUN_RADIUS_KM / norm(&to_sun)).asin();
+    let separation = (dot(&to_sun, &to_earth) / (norm(&to_sun) * norm(&to_earth)))
+        .clamp(-1.0, 1.0)
+        .acos();
+
+    (earth_semidiameter, sun_semidiameter, separation)
+}
+
+pub fn get_illumination(
+    satrec: &satellite::io::Satrec,
+    time: DateTime<Utc>,
+) -> Result<Illumination, PassesCalculationError> {
+    let position_eci = get_position_eci(satrec, time)?;
+    let (earth_semidiameter, sun_semidiameter, separation) =
+        get_shadow_geometry(&position_eci, &get_sun_position_eci(time));
+
+    if separation >= earth_semidiameter + sun_semidiameter {
+        Ok(Illumination::Sunlit)
+    } else if separation > earth_semidiameter - sun_semidiameter {
+        Ok(Illumination::Penumbra)
+    } else {
+        Ok(Illumination::Umbra)
+    }
+}
+
+/// Оценка видимой звездной величины по стандартной величине (1000 км, фазовый угол 90°),
+/// дальности и фазовому углу для диффузно отражающей сферы. None, если спутник в тени Земли
+pub fn get_magnitude(
+    standard_magnitude: f64,
+    satrec: &satellite::io::Satrec,
+    observer: &satellite::Geodedic,
+    time: DateTime<Utc>,
+) -> Result<Option<f64>, PassesCalculationError> {
+    let position_eci = get_position_eci(satrec, time)?;
+    let sun_eci = get_sun_position_eci(time);
+
+    let (earth_semidiameter, sun_semidiameter, separation) =
+        get_shadow_geometry(&position_eci, &sun_eci);
+    if separation <= earth_semidiameter - sun_semidiameter {
+        return Ok(None);
+    }
+
+    let gmst = satellite::propogation::gstime::gstime_datetime(time);
+    let observer_eci = ecf_to_eci(&geodedic_to_ecf(observer), gmst);
+
+    let to_sun = sub(&sun_eci, &position_eci);
+    let to_observer = sub(&observer_eci, &position_eci);
+    let range = norm(&to_observer);
+
+    let phase_angle = (dot(&to_sun, &to_observer) / (norm(&to_sun) * range))
+        .clamp(-1.0, 1.0)
+        .acos();
+    let phase_factor = phase_angle.sin() + (std::f64::consts::PI - phase_angle) * phase_angle.cos();
+
+    if phase_factor <= f64::EPSILON {
+        return Ok(None);
+    }
+
+    Ok(Some(standard_magnitude + 5.0 * (range / 1000.0).log10() - 2.5 * phase_factor.log10()))
+}
+
+/// Положительна, когда спутник вне полной тени и у наблюдателя достаточно темно, в градусах
+fn get_visibility_margin_safe(
+    satrec: &satellite::io::Satrec,
+    observer: &satellite::Geodedic,
+    time: DateTime<Utc>,
+) -> f64 {
+    let position_eci = match get_position_eci(satrec, time) {
+        Ok(position_eci) => position_eci,
+        Err(_) => return f64::NAN
+    };
+
+    let (earth_semidiameter, sun_semidiameter, separation) =
+        get_shadow_geometry(&position_eci, &get_sun_position_eci(time));
+    let shadow_margin =
+        (separation - earth_semidiameter + sun_semidiameter) * satellite::constants::RAD_TO_DEG;
+    let darkness_margin = MAX_SUN_ELEVATION_FOR_VISIBILITY - get_sun_elevation(observer, time);
+
+    f64::min(shadow_margin, darkness_margin)
+}
+
+fn get_visible_intervals(
+    element_sets: &ElementSets,
+    observer: &satellite::Geodedic,
+    rise_time: DateTime<Utc>, fall_time: DateTime<Utc>,
+) -> Result<Vec<VisibleInterval>, PassesCalculationError> {
+    let get_margin = |shift_seconds: f64| -> f64 {
+        let current_time = rise_time + Duration::milliseconds((shift_seconds * 1000.0) as i64);
+        get_visibility_margin_safe(element_sets.at(current_time), observer, current_time)
+    };
+
+    let to_time = |shift_seconds: f64| rise_time + Duration::milliseconds((shift_seconds * 1000.0) as i64);
+
+    let pass_seconds = (fall_time - rise_time).num_milliseconds() as f64 / 1000.0;
+
+    let intervals = get_positive_intervals(get_margin, pass_seconds, VISIBILITY_STEP_SECONDS)?;
+
+    Ok(intervals.into_iter()
+        .map(|(start, end)| VisibleInterval { start_time: to_time(start), end_time: to_time(end) })
+        .collect())
+}
+
+/// Интервалы на [0, end], где fun положительна: перебор с шагом step и уточнение границ методом Брента
+fn get_positive_intervals<F>(fun: F, end: f64, step: f64) -> Result<Vec<(f64, f64)>, PassesCalculationError>
+    where
+        F: Fn(f64) -> f64
+{
+    let mut result = vec![];
+
+    let mut prev_shift = 0.0;
+    let mut prev_value = fun(0.0);
+    let mut positive_since = if prev_value > 0.0 { Some(0.0) } else { None };
+
+    while prev_shift < end {
+        let shift = f64::min(prev_shift + step, end);
+        let curr_value = fun(shift);
+
+        if (curr_value > 0.0) != (prev_value > 0.0) {
+            let crossing = get_root(&fun, prev_shift, shift)?;
+
+            if curr_value > 0.0 {
+                positive_since = Some(crossing);
+            } else if let Some(start) = positive_since.take() {
+                result.push((start, crossing));
+            }
+        }
+
+        prev_shift = shift;
+        prev_value = curr_value;
+    }
+
+    if let Some(start) = positive_since {
+        result.push((start, end));
+    }
+
+    Ok(result)
+}
+
+/// Максимум функции на отрезке, где уже известны значения на концах; возвращает точку и значение в ней
+fn get_max_parab<F>(mut fun: F, (start, f_start): (f64, f64), (end, f_end): (f64, f64), tol: f64) -> (f64, f64)
     where
         F: FnMut(f64) -> f64
 {
@@ -105,9 +597,9 @@ fn get_max_parab<F>(mut fun: F, start: f64, end: f64, tol: f64) -> f64
     let mut c = end;
     let mut b = (a + c) / 2.0;
 
-    let mut f_a = fun(a);
+    let mut f_a = f_start;
     let mut f_b = fun(b);
-    let mut f_c = fun(c);
+    let mut f_c = f_end;
 
     let x = b;
 
@@ -116,19 +608,19 @@ fn get_max_parab<F>(mut fun: F, start: f64, end: f64, tol: f64) -> f64
         let denominator = (b - a) * (f_b - f_c) - (b - c) * (f_b - f_a);
 
         if denominator == 0.0 {
-            return b;
+            return (b, f_b);
         }
 
         let x = x - 0.5 * (numerator / denominator);
 
         if (b - x).abs() <= tol {
-            return x;
+            return (x, fun(x));
         }
 
         let f_x = fun(x);
 
         if f_x > f_b {
-            return b;
+            return (b, f_b);
         }
 
         (a, b, c) = ((a + x) / 2.0, x, (x + c) / 2.0);
@@ -155,161 +647,247 @@ fn get_root<F>(mut fun: F, start: f64, end: f64) -> Result<f64, PassesCalculatio
     }
 }
 
-pub fn get_satellite_passes(
-    satrec: &satellite::io::Satrec,
-    start_time: DateTime<Utc>, duration: Duration,
-    observer: &satellite::Geodedic,
-) -> Result<Vec<PassData>, PassesCalculationError> {
-    let mut get_elevation = |shift_minutes: f64| -> f64 {
-        let current_time = start_time + Duration::seconds((shift_minutes * 60.0) as i64);
-        get_elevation_safe(&satrec, &observer, current_time)
-    };
+/// Верхняя оценка скорости изменения угла места спутника (рад/мин): угловая скорость линии визирования
+/// не больше относительной скорости в перигее, деленной на наименьшую возможную дальность
+fn get_max_elevation_rate(satrec: &satellite::io::Satrec) -> f64 {
+    let mean_motion = satrec.no / 60.0;
+    let semi_major_axis = (EARTH_GM_KM3_PER_SEC2 / mean_motion.powi(2)).cbrt();
+    let perigee_radius = semi_major_axis * (1.0 - satrec.ecco);
+
+    let perigee_speed = (EARTH_GM_KM3_PER_SEC2 * (2.0 / perigee_radius - 1.0 / semi_major_axis)).sqrt();
+    let observer_speed = EARTH_RADIUS_KM * EARTH_ROTATION_RAD_PER_SEC;
+    let min_range = f64::max(perigee_radius - EARTH_RADIUS_KM, MIN_PERIGEE_ALTITUDE_KM);
+
+    ELEVATION_RATE_MARGIN * 60.0 * (perigee_speed + observer_speed) / min_range
+}
 
+/// Пролет, найденный адаптивным поиском: восход, заход (минуты от начала окна) и все точки сетки между ними
+struct ScannedPass {
+    rise_mins: f64,
+    fall_mins: f64,
+    /// Пары (минуты, угол места), включая восход и заход с нулевым углом места
+    samples: Vec<(f64, f64)>,
+}
+
+/// Находит пролеты, целиком попадающие в окно. Шаг адаптивный: угол места не может измениться быстрее
+/// max_elevation_rate, поэтому вдали от горизонта шаг растет пропорционально |угол места|, а у горизонта
+/// не превышает MIN_DETECTABLE_PASS_SECONDS, так что любой пролет такой длительности содержит хотя бы одну точку сетки
+fn scan_passes<F>(
+    mut get_elevation: F,
+    duration_mins: f64,
+    max_elevation_rate: f64,
+) -> Result<Vec<ScannedPass>, PassesCalculationError>
+    where
+        F: FnMut(f64) -> f64
+{
     let mut result = vec![];
+    let mut current_pass: Option<ScannedPass> = None;
 
-    let mut rise_mins = 0.0;
-    let mut rise_time = None;
+    let min_step = MIN_DETECTABLE_PASS_SECONDS / 60.0;
 
+    let mut prev_shift = 0.0;
     let mut prev_elevation = get_elevation(0.0);
 
-    for shift in 1..=duration.num_minutes() {
-        let curr_elevation = get_elevation(shift as f64);
+    while prev_shift < duration_mins {
+        let step = if prev_elevation.is_finite() {
+            (prev_elevation.abs() / max_elevation_rate).clamp(min_step, MAX_SCAN_STEP_MINUTES)
+        } else {
+            min_step
+        };
+
+        let shift = f64::min(prev_shift + step, duration_mins);
+        let curr_elevation = get_elevation(shift);
 
         if curr_elevation.is_sign_positive() != prev_elevation.is_sign_positive() {
-            let horizon_mins = get_root(&mut get_elevation, (shift - 1) as f64, shift as f64)?;
-            let horizon_time = start_time + Duration::seconds((horizon_mins * 60.0) as i64);
+            let horizon_mins = get_root(&mut get_elevation, prev_shift, shift)?;
 
             if prev_elevation.is_sign_negative() {
-                rise_mins = horizon_mins;
-                rise_time = Some(horizon_time);
-            } else {
-                let fall_mins = horizon_mins;
-                let fall_time = horizon_time;
-
-                if let Some(rt) = rise_time {
-                    let mut max_elev = 0.0;
-                    let mut middle_mins = 0;
-
-                    for i in rise_mins as i64..=fall_mins as i64 {
-                        let curr_elev = get_elevation(i as f64);
-
-                        if curr_elev > max_elev {
-                            max_elev = curr_elev;
-                            middle_mins = i;
-                        }
-                    }
-
-                    let apogee_mins = get_max_parab(
-                        |x| { -get_elevation(x) },
-                        f64::max(rise_mins, (middle_mins - 1) as f64),
-                        f64::min(fall_mins, (middle_mins + 1) as f64),
-                        0.001 / 60.0,
-                    );
-
-                    let apogee_elevation = get_elevation(apogee_mins) * satellite::constants::RAD_TO_DEG;
-                    let apogee_time = start_time + Duration::seconds((60.0 * apogee_mins) as i64);
-
-                    let rise_azimuth = get_observer_look(&satrec, rt, &observer)?.azimuth;
-                    let fall_azimuth = get_observer_look(&satrec, fall_time, &observer)?.azimuth;
-                    let apogee_azimuth = get_observer_look(&satrec, apogee_time, &observer)?.azimuth;
-
-                    let pass = PassData {
-                        satellite_name: satrec.name.clone().unwrap_or("N/A".to_string()),
-                        rise_time: rt,
-                        rise_azimuth,
-                        apogee_time,
-                        apogee_elevation,
-                        apogee_azimuth,
-                        fall_time,
-                        fall_azimuth,
-                    };
-
-                    result.push(pass);
-                    rise_time = None;
-                }
+                current_pass = Some(ScannedPass {
+                    rise_mins: horizon_mins,
+                    fall_mins: horizon_mins,
+                    samples: vec![(horizon_mins, 0.0), (shift, curr_elevation)],
+                });
+            } else if let Some(mut scanned_pass) = current_pass.take() {
+                scanned_pass.fall_mins = horizon_mins;
+                scanned_pass.samples.push((horizon_mins, 0.0));
+                result.push(scanned_pass);
             }
+        } else if let Some(scanned_pass) = &mut current_pass {
+            scanned_pass.samples.push((shift, curr_elevation));
         }
 
+        prev_shift = shift;
         prev_elevation = curr_elevation;
     }
 
     Ok(result)
 }
 
+/// Момент через shift_minutes минут после start_time, округленный до миллисекунды.
+/// satellite-rs принимает время с точностью до миллисекунды, поэтому точнее его задавать бессмысленно
+fn shift_time(start_time: DateTime<Utc>, shift_minutes: f64) -> DateTime<Utc> {
+    start_time + Duration::milliseconds((shift_minutes * 60_000.0).round() as i64)
+}
+
+/// Пролеты спутника над горизонтом. Моменты восхода, кульминации и захода возвращаются с точностью
+/// до миллисекунды: корни ищутся методом Брента до сходимости много точнее миллисекунды, а
+/// кульминация уточняется параболической интерполяцией с допуском в 1 мс.
+///
+/// С моделью рефракции восход, заход и угол места в кульминации считаются по видимому углу места.
+/// Как выбирается шаг поиска, описано у scan_passes
+pub fn get_satellite_passes(
+    element_sets: &ElementSets,
+    start_time: DateTime<Utc>, duration: Duration,
+    observer: &satellite::Geodedic,
+    standard_magnitude: Option<f64>,
+    refraction: Option<Refraction>,
+) -> Result<Vec<PassData>, PassesCalculationError> {
+    let satrec = element_sets.current();
+
+    let mut get_elevation = |shift_minutes: f64| -> f64 {
+        let time = shift_time(start_time, shift_minutes);
+        apply_refraction(get_elevation_safe(element_sets.at(time), observer, time), refraction)
+    };
+
+    let mut result = vec![];
+
+    let duration_mins = duration.num_milliseconds() as f64 / 60_000.0;
+
+    for scanned_pass in scan_passes(&mut get_elevation, duration_mins, get_max_elevation_rate(satrec))? {
+        let samples = &scanned_pass.samples;
+        let middle_index = samples.iter()
+            .enumerate()
+            .max_by(|(_, (_, a)), (_, (_, b))| a.total_cmp(b))
+            .map(|(index, _)| index)
+            .expect("Scanned pass always has samples");
+
+        // Соседние точки сетки поиска ограничивают кульминацию, их значения уже посчитаны
+        let (before_mins, before_elevation) = samples[middle_index.saturating_sub(1)];
+        let (after_mins, after_elevation) = samples[usize::min(middle_index + 1, samples.len() - 1)];
+
+        let (apogee_mins, apogee_elevation) = get_max_parab(
+            |x| { -get_elevation(x) },
+            (before_mins, -before_elevation),
+            (after_mins, -after_elevation),
+            0.001 / 60.0,
+        );
+
+        let apogee_elevation = -apogee_elevation * satellite::constants::RAD_TO_DEG;
+        let apogee_time = shift_time(start_time, apogee_mins);
+        let rt = shift_time(start_time, scanned_pass.rise_mins);
+        let fall_time = shift_time(start_time, scanned_pass.fall_mins);
+
+        let rise_azimuth = get_observer_look(element_sets.at(rt), rt, observer)?.azimuth;
+        let fall_azimuth = get_observer_look(element_sets.at(fall_time), fall_time, observer)?.azimuth;
+        let apogee_azimuth = get_observer_look(element_sets.at(apogee_time), apogee_time, observer)?.azimuth;
+
+        let illumination = get_illumination(element_sets.at(apogee_time), apogee_time)?;
+        let observer_lighting = ObserverLighting::from_sun_elevation(
+            get_sun_elevation(observer, apogee_time),
+        );
+        let visible_intervals = get_visible_intervals(element_sets, observer, rt, fall_time)?;
+        let apogee_magnitude = match standard_magnitude {
+            Some(standard_magnitude) => {
+                get_magnitude(standard_magnitude, element_sets.at(apogee_time), observer, apogee_time)?
+            }
+            None => None,
+        };
+
+        let pass = PassData {
+            satellite_name: satrec.name.clone().unwrap_or("N/A".to_string()),
+            norad_id: satrec.satnum.clone(),
+            rise_time: rt,
+            rise_azimuth,
+            apogee_time,
+            apogee_elevation,
+            apogee_azimuth,
+            fall_time,
+            fall_azimuth,
+            illumination,
+            observer_lighting,
+            visible_intervals,
+            apogee_magnitude,
+        };
+
+        result.push(pass);
+    }
+
+    Ok(result)
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn get_filtered_passes(
-    satrecs: Vec<satellite::io::Satrec>,
+    satellites: &[ElementSets],
     start_time: DateTime<Utc>, duration: Duration,
     min_elevation: f64, min_apogee: f64,
     observer: &satellite::Geodedic,
+    standard_magnitudes: &StandardMagnitudes,
+    horizon_mask: Option<&HorizonMask>,
+    refraction: Option<Refraction>,
 ) -> Result<Vec<PassData>, PassesCalculationError> {
     let min_elevation_rad = min_elevation * satellite::constants::DEG_2_RAD;
 
     let mut all_passes = vec![];
 
-    for satrec in satrecs {
+    for element_sets in satellites {
         let passes = get_satellite_passes(
-            &satrec,
+            element_sets,
             start_time, duration,
-            &observer,
+            observer,
+            standard_magnitudes.get(element_sets.current()),
+            refraction,
         )?;
 
-        let mut passes: Vec<PassData> = passes.into_iter()
+        let passes: Vec<PassData> = passes.into_iter()
             .filter(|pass_data| {
                 pass_data.apogee_elevation >= min_apogee &&
                     pass_data.apogee_elevation >= min_elevation
             })
             .collect();
 
-        for pass_data in &mut passes {
+        let mut clear_passes = vec![];
+
+        for mut pass_data in passes {
             let rise_time = pass_data.rise_time;
 
-            let get_elevation = |shift_minutes: f64| -> f64 {
-                let current_time = rise_time + Duration::seconds((shift_minutes * 60.0) as i64);
-                get_elevation_safe(&satrec, &observer, current_time)
+            let get_margin = |shift_minutes: f64| -> f64 {
+                let time = shift_time(rise_time, shift_minutes);
+                get_mask_margin_safe(element_sets.at(time), observer, time, min_elevation_rad, horizon_mask, refraction)
             };
 
-            let mut prev_elevation = get_elevation(0.0);
-            let pass_duration = (pass_data.fall_time - pass_data.rise_time).num_seconds() as f64 / 60.0;
+            // Маска может закрывать спутник посреди пролета, поэтому с ней шаг мельче
+            let step = if horizon_mask.is_some() { VISIBILITY_STEP_SECONDS / 60.0 } else { PASS_SCAN_STEP_MINUTES };
+            let pass_duration = (pass_data.fall_time - pass_data.rise_time).num_milliseconds() as f64 / 60_000.0;
 
-            for shift in 1..=pass_duration as i64 {
-                let current_elevation = get_elevation(shift as f64);
+            let clear_intervals: Vec<_> = get_positive_intervals(get_margin, pass_duration, step)?
+                .into_iter()
+                .map(|(start, end)| (shift_time(rise_time, start), shift_time(rise_time, end)))
+                .collect();
 
-                if prev_elevation <= min_elevation_rad && min_elevation_rad <= current_elevation {
-                    let rise_mins = get_root(
-                        |shift| { get_elevation(shift) - min_elevation_rad },
-                        (shift - 1) as f64,
-                        shift as f64,
-                    )?;
-
-                    pass_data.rise_time += Duration::seconds((rise_mins * 60.0) as i64);
-                    break;
-                }
-
-                prev_elevation = current_elevation;
-            }
-
-            prev_elevation = get_elevation(pass_duration);
+            // Пролет целиком закрыт маской или ниже минимального угла места
+            let (Some(&(first_start, _)), Some(&(_, last_end))) =
+                (clear_intervals.first(), clear_intervals.last()) else {
+                continue;
+            };
 
-            for shift in (0..=pass_duration as i64 - 1).rev() {
-                let current_elevation = get_elevation(shift as f64) - min_elevation_rad;
+            pass_data.rise_time = first_start;
+            pass_data.fall_time = last_end;
 
-                if prev_elevation.is_sign_negative() && current_elevation.is_sign_positive() {
-                    let fall_mins = get_root(
-                        |shift| { get_elevation(shift) - min_elevation_rad },
-                        (shift + 2) as f64,  // +2 на случай если длительность пролета дробная и
-                        shift as f64,        // момент смены знака приходиться на дробную чатсть
-                    )?;
+            pass_data.visible_intervals = std::mem::take(&mut pass_data.visible_intervals).into_iter()
+                .flat_map(|interval| {
+                    clear_intervals.iter().filter_map(move |&(clear_start, clear_end)| {
+                        let start_time = interval.start_time.max(clear_start);
+                        let end_time = interval.end_time.min(clear_end);
 
-                    let fall_mins_from_end = pass_duration - fall_mins;
-                    pass_data.fall_time -= Duration::seconds((fall_mins_from_end * 60.0) as i64);
-                    break;
-                }
+                        (start_time < end_time).then_some(VisibleInterval { start_time, end_time })
+                    })
+                })
+                .collect();
 
-                prev_elevation = current_elevation;
-            }
+            clear_passes.push(pass_data);
         }
 
-        all_passes.extend(passes);
+        all_passes.extend(clear_passes);
     }
 
     all_passes.sort_by_key(|pass_data| pass_data.rise_time);
@@ -318,15 +896,24 @@ pub fn get_filtered_passes(
 }
 
 pub fn get_trajectory(
-    satrec: &satellite::io::Satrec,
+    element_sets: &ElementSets,
+    start_time: DateTime<Utc>, duration: Duration,
+) -> Result<Vec<satellite::Geodedic>, PassesCalculationError> {
+    get_sampled_trajectory(element_sets, start_time, duration, 1)
+}
+
+/// Точки трассы с шагом step_seconds начиная с start_time, без расчета промежуточных секунд
+pub fn get_sampled_trajectory(
+    element_sets: &ElementSets,
     start_time: DateTime<Utc>, duration: Duration,
+    step_seconds: usize,
 ) -> Result<Vec<satellite::Geodedic>, PassesCalculationError> {
     let mut result = vec![];
 
-    for shift in 0..duration.num_seconds() {
+    for shift in (0..duration.num_seconds()).step_by(step_seconds) {
         let current_time = start_time + Duration::seconds(shift);
 
-        let sat_pos = get_satellite_pos(&satrec, current_time)?;
+        let sat_pos = get_satellite_pos(element_sets.at(current_time), current_time)?;
 
         result.push(sat_pos);
     }
@@ -335,20 +922,120 @@ pub fn get_trajectory(
 }
 
 pub fn get_observer_trajectory(
-    satrec: &satellite::io::Satrec,
+    element_sets: &ElementSets,
     start_time: DateTime<Utc>, duration: Duration,
     observer: &satellite::Geodedic,
-) -> Result<Vec<satellite::Bearing>, PassesCalculationError> {
+    standard_magnitude: Option<f64>,
+    refraction: Option<Refraction>,
+) -> Result<Vec<LookSample>, PassesCalculationError> {
     let mut result = vec![];
 
     for shift in 1..=duration.num_seconds() {
         let current_time = start_time + Duration::seconds(shift);
+        let satrec = element_sets.at(current_time);
 
-        let look_angles = get_observer_look(&satrec, current_time, observer)?;
+        let mut look_angles = get_observer_look(satrec, current_time, observer)?;
+        if let Some(refraction) = refraction {
+            look_angles.elevation = refraction.apply(look_angles.elevation);
+        }
 
-        result.push(look_angles);
+        let (range, range_rate) = get_range_and_range_rate(satrec, observer, current_time)?;
+
+        let magnitude = match standard_magnitude {
+            Some(standard_magnitude) if look_angles.elevation > 0.0 => {
+                get_magnitude(standard_magnitude, satrec, observer, current_time)?
+            }
+            _ => None,
+        };
+
+        result.push(LookSample {
+            azimuth: look_angles.azimuth,
+            elevation: look_angles.elevation,
+            range,
+            range_rate,
+            magnitude,
+        });
     }
 
     Ok(result)
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Угол места (рад) пролета с кульминацией peak в момент apogee_mins; вдали от кульминации он меняется
+    /// со скоростью rate рад/мин, как у низкоорбитального спутника, проходящего у самого горизонта
+    fn grazing_pass(apogee_mins: f64, peak: f64, rate: f64) -> impl Fn(f64) -> f64 {
+        const SMOOTHING_MINUTES: f64 = 0.01;
+
+        move |shift_minutes| {
+            let distance = (shift_minutes - apogee_mins).hypot(SMOOTHING_MINUTES);
+            peak + rate * (SMOOTHING_MINUTES - distance)
+        }
+    }
+
+    /// Половина длительности пролета grazing_pass в минутах
+    fn grazing_pass_half_duration(peak: f64, rate: f64) -> f64 {
+        const SMOOTHING_MINUTES: f64 = 0.01;
+
+        ((SMOOTHING_MINUTES + peak / rate).powi(2) - SMOOTHING_MINUTES.powi(2)).sqrt()
+    }
+
+    #[test]
+    fn finds_grazing_pass_shorter_than_old_fixed_step() {
+        // Пролет около 40 с с кульминацией в 0.6° между минутными точками старой сетки
+        let (apogee_mins, peak, rate) = (30.5, 0.01, 0.03);
+        let elevation = grazing_pass(apogee_mins, peak, rate);
+        let half_duration = grazing_pass_half_duration(peak, rate);
+
+        assert!(half_duration * 2.0 * 60.0 < 60.0);
+        assert!((0..=60).all(|minute| elevation(minute as f64) < 0.0), "Old 1 minute grid shouldn't see the pass");
+
+        let passes = scan_passes(&elevation, 60.0, 0.05).unwrap();
+
+        assert_eq!(passes.len(), 1);
+        assert!((passes[0].rise_mins - (apogee_mins - half_duration)).abs() < 1e-6);
+        assert!((passes[0].fall_mins - (apogee_mins + half_duration)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn finds_pass_shorter_than_min_detectable_at_grid_point() {
+        // Скорость изменения угла места заведомо велика, поэтому сетка равномерная с шагом в 20 с,
+        // и пролет около 8 с приходится ровно на ее точку
+        let min_step = MIN_DETECTABLE_PASS_SECONDS / 60.0;
+        let (apogee_mins, peak, rate) = (30.0 * min_step, 0.002, 0.03);
+        let elevation = grazing_pass(apogee_mins, peak, rate);
+        let half_duration = grazing_pass_half_duration(peak, rate);
+
+        assert!(half_duration * 2.0 * 60.0 < MIN_DETECTABLE_PASS_SECONDS);
+
+        let passes = scan_passes(&elevation, 60.0, 1000.0).unwrap();
+
+        assert_eq!(passes.len(), 1);
+        assert!((passes[0].fall_mins - passes[0].rise_mins - 2.0 * half_duration).abs() < 1e-6);
+    }
+
+    #[test]
+    fn scan_samples_bracket_apogee() {
+        let (apogee_mins, peak, rate) = (12.3, 0.5, 0.05);
+        let elevation = grazing_pass(apogee_mins, peak, rate);
+
+        let passes = scan_passes(&elevation, 60.0, 0.05).unwrap();
+        let samples = &passes[0].samples;
+
+        assert_eq!(samples.first().unwrap().0, passes[0].rise_mins);
+        assert_eq!(samples.last().unwrap().0, passes[0].fall_mins);
+        assert!(samples.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(samples.iter().any(|&(shift, _)| shift <= apogee_mins));
+        assert!(samples.iter().any(|&(shift, _)| shift >= apogee_mins));
+    }
+
+    #[test]
+    fn skips_pass_cut_by_window_end() {
+        let elevation = grazing_pass(59.9, 0.1, 0.05);
+
+        assert!(scan_passes(&elevation, 60.0, 0.05).unwrap().is_empty());
+    }
+}
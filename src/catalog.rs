@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+use super::calculations;
+
+#[derive(Debug, Error)]
+pub enum CatalogError {
+    #[error("Failed to open tle at the specified path")]
+    TleLoadingFailed(#[from] std::io::Error),
+    #[error("Failed to find the specified satellite in the catalog")]
+    SatelliteNotFound,
+    #[error("Satellite name {query:?} matches several objects, use one of NORAD ids: {}", .norad_ids.join(", "))]
+    AmbiguousSatellite { query: String, norad_ids: Vec<String> },
+}
+
+/// Распарсенный набор элементов, проиндексированный по имени, номеру NORAD и обозначению COSPAR
+pub struct Catalog {
+    satrecs: Vec<satellite::io::Satrec>,
+    international_designators: HashMap<String, String>,
+    revolution_numbers: HashMap<String, u32>,
+    by_name: HashMap<String, Vec<usize>>,
+    by_norad_id: HashMap<String, usize>,
+    by_international_designator: HashMap<String, usize>,
+}
+
+/// Буквы Alpha-5 вместо двух старших цифр номера NORAD: A - 10, B - 11 и т. д. без I и O.
+/// Последняя буква Z дает 33, поэтому в tle помещаются номера не больше 339999
+pub const ALPHA5_LETTERS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
+
+/// Номер NORAD из формата Alpha-5, например "A0001" - это 100001
+fn decode_alpha5(norad_id: &str) -> Option<u32> {
+    let (letter, digits) = norad_id.split_at_checked(1)?;
+    let letter = letter.to_ascii_uppercase().bytes().next()?;
+
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let high = ALPHA5_LETTERS.iter().position(|&alpha5_letter| alpha5_letter == letter)? as u32 + 10;
+
+    Some(high * 10_000 + digits.parse::<u32>().ok()?)
+}
+
+/// Приводит номер NORAD к виду без ведущих нулей, чтобы "00900" и "900" совпадали;
+/// номера Alpha-5 раскрываются в число, чтобы "A0001" и "100001" тоже совпадали
+pub fn normalize_norad_id(norad_id: &str) -> String {
+    let norad_id = norad_id.trim();
+
+    if let Some(decoded) = decode_alpha5(norad_id) {
+        return decoded.to_string();
+    }
+
+    let trimmed = norad_id.trim_start_matches('0');
+
+    if trimmed.is_empty() { "0".to_string() } else { trimmed.to_string() }
+}
+
+/// Приводит обозначение COSPAR к виду из tle, например "1998-067A" к "98067A"
+fn normalize_international_designator(designator: &str) -> String {
+    let designator = designator.trim().to_uppercase();
+
+    match designator.split_once('-') {
+        Some((year, rest)) if year.len() == 4 && year.chars().all(|c| c.is_ascii_digit()) => {
+            format!("{}{}", &year[2..], rest)
+        }
+        _ => designator,
+    }
+}
+
+/// NORAD id в tle может быть в формате Alpha-5, где первая цифра заменена буквой
+fn looks_like_norad_id(query: &str) -> bool {
+    let mut chars = query.chars();
+
+    match chars.next() {
+        Some(first) if first.is_ascii_alphanumeric() => {
+            query.len() <= 9 && chars.all(|c| c.is_ascii_digit()) &&
+                (first.is_ascii_digit() || query.len() == 5)
+        }
+        _ => false,
+    }
+}
+
+/// Обозначения COSPAR из первой строки tle (колонки 10-17), satellite-rs их не сохраняет
+fn parse_international_designators(tle: &str) -> HashMap<String, String> {
+    let mut designators = HashMap::new();
+
+    for line in tle.lines() {
+        if !line.starts_with("1 ") {
+            continue;
+        }
+
+        if let (Some(norad_id), Some(designator)) = (line.get(2..7), line.get(9..17)) {
+            let designator = designator.trim();
+
+            if !designator.is_empty() {
+                designators.insert(normalize_norad_id(norad_id), designator.to_string());
+            }
+        }
+    }
+
+    designators
+}
+
+/// Номер витка на эпоху из второй строки tle (колонки 64-68), satellite-rs его тоже не сохраняет
+fn parse_revolution_numbers(tle: &str) -> HashMap<String, u32> {
+    let mut revolution_numbers = HashMap::new();
+
+    for line in tle.lines() {
+        if !line.starts_with("2 ") {
+            continue;
+        }
+
+        if let (Some(norad_id), Some(revolution_number)) = (line.get(2..7), line.get(63..68)) {
+            if let Ok(revolution_number) = revolution_number.trim().parse() {
+                revolution_numbers.insert(normalize_norad_id(norad_id), revolution_number);
+            }
+        }
+    }
+
+    revolution_numbers
+}
+
+impl Catalog {
+    pub fn parse(tle: &str) -> Self {
+        let (satrecs, _errors) = satellite::io::parse_multiple(tle);
+        let international_designators = parse_international_designators(tle);
+        let revolution_numbers = parse_revolution_numbers(tle);
+
+        let mut by_name: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut by_norad_id = HashMap::new();
+        let mut by_international_designator = HashMap::new();
+
+        for (index, satrec) in satrecs.iter().enumerate() {
+            let norad_id = normalize_norad_id(&satrec.satnum);
+
+            if let Some(name) = &satrec.name {
+                by_name.entry(name.trim().to_string()).or_default().push(index);
+            }
+
+            if let Some(designator) = international_designators.get(&norad_id) {
+                by_international_designator.entry(designator.clone()).or_insert(index);
+            }
+
+            by_norad_id.entry(norad_id).or_insert(index);
+        }
+
+        Catalog {
+            satrecs,
+            international_designators,
+            revolution_numbers,
+            by_name,
+            by_norad_id,
+            by_international_designator,
+        }
+    }
+
+    pub fn load(tle_file_path: &str) -> Result<Self, CatalogError> {
+        let tle = std::fs::read_to_string(tle_file_path)?;
+        Ok(Catalog::parse(&tle))
+    }
+
+    /// Имена спутников в порядке файла, а для наборов без имени - номера NORAD
+    pub fn satellite_names(&self) -> Vec<String> {
+        self.satrecs.iter()
+            .map(|satrec| match &satrec.name {
+                Some(name) => name.trim().to_string(),
+                None => normalize_norad_id(&satrec.satnum),
+            })
+            .collect()
+    }
+
+    pub fn satellites_count(&self) -> usize {
+        self.satrecs.len()
+    }
+
+    pub fn international_designator(&self, satrec: &satellite::io::Satrec) -> Option<&str> {
+        self.international_designators.get(&normalize_norad_id(&satrec.satnum)).map(String::as_str)
+    }
+
+    /// Номер витка спутника в момент пролета над наблюдателем на широте observer_lat по текущему набору элементов
+    pub fn revolution_number(&self, norad_id: &str, time: DateTime<Utc>, observer_lat: f64) -> Option<i64> {
+        let norad_id = normalize_norad_id(norad_id);
+        let satrec = &self.satrecs[*self.by_norad_id.get(&norad_id)?];
+        let revolution_at_epoch = *self.revolution_numbers.get(&norad_id)?;
+
+        Some(calculations::get_revolution_number(satrec, revolution_at_epoch, time, observer_lat))
+    }
+
+    /// Ищет спутник по номеру NORAD, обозначению COSPAR или имени (без учета пробелов по краям)
+    pub fn find_satrec(&self, query: &str) -> Result<&satellite::io::Satrec, CatalogError> {
+        let query = query.trim();
+
+        if looks_like_norad_id(query) {
+            if let Some(&index) = self.by_norad_id.get(&normalize_norad_id(query)) {
+                return Ok(&self.satrecs[index]);
+            }
+        }
+
+        let designator = normalize_international_designator(query);
+        if let Some(&index) = self.by_international_designator.get(&designator) {
+            return Ok(&self.satrecs[index]);
+        }
+
+        match self.by_name.get(query).map(Vec::as_slice) {
+            Some([index]) => Ok(&self.satrecs[*index]),
+            Some(indices) if !indices.is_empty() => Err(CatalogError::AmbiguousSatellite {
+                query: query.to_string(),
+                norad_ids: indices.iter()
+                    .map(|&index| normalize_norad_id(&self.satrecs[index].satnum))
+                    .collect(),
+            }),
+            _ => Err(CatalogError::SatelliteNotFound),
+        }
+    }
+}
+
+/// Общий для всех обработчиков каталог, который подменяется целиком после каждой загрузки tle
+pub struct CatalogStore {
+    current: RwLock<Arc<Catalog>>,
+}
+
+impl CatalogStore {
+    pub fn new(catalog: Catalog) -> Self {
+        CatalogStore { current: RwLock::new(Arc::new(catalog)) }
+    }
+
+    pub fn snapshot(&self) -> Arc<Catalog> {
+        self.current.read()
+            .expect("Catalog lock shouldn't be poisoned")
+            .clone()
+    }
+
+    pub fn replace(&self, catalog: Catalog) {
+        *self.current.write().expect("Catalog lock shouldn't be poisoned") = Arc::new(catalog);
+    }
+}
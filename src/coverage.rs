@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+
+use super::calculations::PassData;
+use super::serializers::SerializablePassData;
+
+/// Пролет над одной из станций сети
+#[derive(Serialize)]
+pub struct StationPass {
+    pub station_id: String,
+    #[serde(flatten)]
+    pub pass_data: SerializablePassData,
+}
+
+/// Интервал, когда спутник виден хотя бы с одной станции
+#[derive(Serialize, Debug)]
+pub struct CoverageInterval {
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub stations: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct SatelliteCoverage {
+    pub satellite_name: String,
+    pub norad_id: String,
+    pub intervals: Vec<CoverageInterval>,
+    /// Суммарное время связи по дням UTC, пересечения пролетов над разными станциями считаются один раз
+    pub contact_minutes_by_day: BTreeMap<NaiveDate, f64>,
+}
+
+/// Объединяет пересекающиеся пролеты одного спутника над разными станциями
+fn merge_intervals(mut passes: Vec<(&str, &PassData)>) -> Vec<CoverageInterval> {
+    passes.sort_by_key(|(_, pass_data)| pass_data.rise_time);
+
+    let mut result: Vec<CoverageInterval> = vec![];
+
+    for (station_id, pass_data) in passes {
+        match result.last_mut() {
+            Some(last) if pass_data.rise_time <= last.end_time => {
+                last.end_time = last.end_time.max(pass_data.fall_time);
+
+                if !last.stations.iter().any(|station| station == station_id) {
+                    last.stations.push(station_id.to_string());
+                }
+            }
+            _ => result.push(CoverageInterval {
+                start_time: pass_data.rise_time,
+                end_time: pass_data.fall_time,
+                stations: vec![station_id.to_string()],
+            }),
+        }
+    }
+
+    result
+}
+
+/// Раскладывает интервалы по дням UTC, разрезая те, что переходят через полночь
+fn get_contact_minutes_by_day(intervals: &[CoverageInterval]) -> BTreeMap<NaiveDate, f64> {
+    let mut result = BTreeMap::new();
+
+    for interval in intervals {
+        let mut start_time = interval.start_time;
+
+        while start_time < interval.end_time {
+            let day = start_time.date_naive();
+            let next_midnight = day.succ_opt()
+                .expect("Next day should exist")
+                .and_hms_opt(0, 0, 0)
+                .expect("Midnight should exist")
+                .and_utc();
+            let end_time = interval.end_time.min(next_midnight);
+
+            *result.entry(day).or_insert(0.0) += (end_time - start_time).num_milliseconds() as f64 / 60_000.0;
+            start_time = end_time;
+        }
+    }
+
+    result
+}
+
+/// Покрытие сетью станций для каждого спутника, встречающегося в пролетах
+pub fn get_network_coverage(passes: &[(String, PassData)]) -> Vec<SatelliteCoverage> {
+    let mut by_satellite: BTreeMap<&str, Vec<(&str, &PassData)>> = BTreeMap::new();
+
+    for (station_id, pass_data) in passes {
+        by_satellite.entry(pass_data.norad_id.as_str()).or_default().push((station_id.as_str(), pass_data));
+    }
+
+    by_satellite.into_values()
+        .map(|satellite_passes| {
+            let (_, first_pass) = satellite_passes[0];
+            let satellite_name = first_pass.satellite_name.trim().to_string();
+            let norad_id = first_pass.norad_id.clone();
+
+            let intervals = merge_intervals(satellite_passes);
+            let contact_minutes_by_day = get_contact_minutes_by_day(&intervals);
+
+            SatelliteCoverage { satellite_name, norad_id, intervals, contact_minutes_by_day }
+        })
+        .collect()
+}
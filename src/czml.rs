@@ -0,0 +1,136 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+
+use super::calculations::PassData;
+
+const SATELLITE_COLORS: [[u8; 4]; 6] = [
+    [255, 255, 0, 255],
+    [0, 255, 255, 255],
+    [255, 0, 255, 255],
+    [0, 255, 0, 255],
+    [255, 128, 0, 255],
+    [128, 160, 255, 255],
+];
+
+pub struct CzmlSatellite<'a> {
+    pub norad_id: String,
+    pub name: String,
+    /// Точки трассы с шагом step_seconds начиная с начала окна, как их возвращает get_sampled_trajectory
+    pub trajectory: Vec<satellite::Geodedic>,
+    pub passes: Vec<&'a PassData>,
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn format_interval(start_time: DateTime<Utc>, end_time: DateTime<Utc>) -> String {
+    format!("{}/{}", format_time(start_time), format_time(end_time))
+}
+
+/// Документ CZML: часы сцены, наземная станция, положения спутников с эпохой начала окна
+/// и линии связи со станцией, доступные только во время пролетов
+pub fn render_document(
+    start_time: DateTime<Utc>, end_time: DateTime<Utc>,
+    observer_lat: f64, observer_lon: f64, observer_alt: f64,
+    satellites: &[CzmlSatellite],
+    step_seconds: usize,
+) -> Value {
+    let window = format_interval(start_time, end_time);
+
+    let mut packets = vec![
+        json!({
+            "id": "document",
+            "name": "Orbitalik",
+            "version": "1.0",
+            "clock": {
+                "interval": window,
+                "currentTime": format_time(start_time),
+                "multiplier": 60,
+                "range": "LOOP_STOP",
+                "step": "SYSTEM_CLOCK_MULTIPLIER",
+            },
+        }),
+        json!({
+            "id": "ground-station",
+            "name": "Ground station",
+            "availability": window,
+            "position": {
+                "cartographicDegrees": [observer_lon, observer_lat, observer_alt],
+            },
+            "point": {
+                "pixelSize": 8,
+                "color": {"rgba": [255, 255, 255, 255]},
+            },
+            "label": {
+                "text": "Ground station",
+                "font": "11pt sans-serif",
+                "pixelOffset": {"cartesian2": [0, -16]},
+            },
+        }),
+    ];
+
+    for (index, satellite_data) in satellites.iter().enumerate() {
+        let color = SATELLITE_COLORS[index % SATELLITE_COLORS.len()];
+        let satellite_id = format!("satellite-{}", satellite_data.norad_id);
+
+        // Время в секундах от эпохи, затем долгота, широта в градусах и высота в метрах
+        let samples: Vec<f64> = satellite_data.trajectory.iter().enumerate()
+            .flat_map(|(index, position)| {
+                [(index * step_seconds) as f64, position.longitude, position.latitude, position.height * 1000.0]
+            })
+            .collect();
+
+        packets.push(json!({
+            "id": satellite_id,
+            "name": satellite_data.name,
+            "availability": window,
+            "position": {
+                "epoch": format_time(start_time),
+                "cartographicDegrees": samples,
+                "interpolationAlgorithm": "LAGRANGE",
+                "interpolationDegree": 5,
+            },
+            "point": {
+                "pixelSize": 6,
+                "color": {"rgba": color},
+            },
+            "path": {
+                "width": 1,
+                "leadTime": 0,
+                "trailTime": 5400,
+                "material": {"solidColor": {"color": {"rgba": color}}},
+            },
+            "label": {
+                "text": satellite_data.name,
+                "font": "10pt sans-serif",
+                "pixelOffset": {"cartesian2": [0, -14]},
+                "fillColor": {"rgba": color},
+            },
+        }));
+
+        if satellite_data.passes.is_empty() {
+            continue;
+        }
+
+        let pass_intervals: Vec<String> = satellite_data.passes.iter()
+            .map(|pass_data| format_interval(pass_data.rise_time, pass_data.fall_time))
+            .collect();
+
+        packets.push(json!({
+            "id": format!("link-{}", satellite_data.norad_id),
+            "name": format!("{} passes", satellite_data.name),
+            "availability": pass_intervals,
+            "polyline": {
+                "width": 2,
+                "arcType": "NONE",
+                "material": {"solidColor": {"color": {"rgba": color}}},
+                "positions": {
+                    "references": ["ground-station#position", format!("{}#position", satellite_id)],
+                },
+            },
+        }));
+    }
+
+    Value::Array(packets)
+}
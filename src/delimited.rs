@@ -0,0 +1,152 @@
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use serde::Serialize;
+
+use super::calculations::PassData;
+use super::serializers::{SerializableBearing, SerializableGeodedic};
+
+/// Разделитель табличного вывода: CSV для электронных таблиц, TSV для pandas и awk
+#[derive(Debug, Clone, Copy)]
+pub enum Delimiter {
+    Comma,
+    Tab,
+}
+
+impl Delimiter {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Delimiter::Comma => "text/csv; charset=utf-8",
+            Delimiter::Tab => "text/tab-separated-values; charset=utf-8",
+        }
+    }
+
+    fn escape(self, field: &str) -> String {
+        match self {
+            Delimiter::Comma if field.contains([',', '"', '\n', '\r']) => {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            }
+            Delimiter::Comma => field.to_string(),
+            // В TSV нет экранирования, поэтому разделители внутри значения заменяются пробелами
+            Delimiter::Tab => field.replace(['\t', '\n', '\r'], " "),
+        }
+    }
+
+    fn push_row(self, output: &mut String, fields: &[String]) {
+        let separator = match self {
+            Delimiter::Comma => ",",
+            Delimiter::Tab => "\t",
+        };
+
+        let row: Vec<String> = fields.iter().map(|field| self.escape(field)).collect();
+        output.push_str(&row.join(separator));
+        output.push('\n');
+    }
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn format_optional(value: Option<f64>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+/// Имя варианта перечисления в том же виде, что и в JSON ответе
+fn format_variant<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => String::new(),
+    }
+}
+
+fn to_fields(fields: &[&str]) -> Vec<String> {
+    fields.iter().map(|field| field.to_string()).collect()
+}
+
+pub fn render_passes(passes: &[PassData], delimiter: Delimiter) -> String {
+    let mut output = String::new();
+
+    delimiter.push_row(&mut output, &to_fields(&[
+        "satellite_name", "norad_id",
+        "rise_time", "rise_azimuth",
+        "apogee_time", "apogee_elevation", "apogee_azimuth",
+        "fall_time", "fall_azimuth",
+        "illumination", "observer_lighting", "visible_intervals", "apogee_magnitude",
+    ]));
+
+    for pass_data in passes {
+        // Интервалы видимости записываются в нотации ISO 8601 "начало/конец" через пробел
+        let visible_intervals: Vec<String> = pass_data.visible_intervals.iter()
+            .map(|interval| {
+                format!("{}/{}", format_time(interval.start_time), format_time(interval.end_time))
+            })
+            .collect();
+
+        delimiter.push_row(&mut output, &[
+            pass_data.satellite_name.trim().to_string(),
+            pass_data.norad_id.clone(),
+            format_time(pass_data.rise_time),
+            pass_data.rise_azimuth.to_string(),
+            format_time(pass_data.apogee_time),
+            pass_data.apogee_elevation.to_string(),
+            pass_data.apogee_azimuth.to_string(),
+            format_time(pass_data.fall_time),
+            pass_data.fall_azimuth.to_string(),
+            format_variant(&pass_data.illumination),
+            format_variant(&pass_data.observer_lighting),
+            visible_intervals.join(" "),
+            format_optional(pass_data.apogee_magnitude),
+        ]);
+    }
+
+    output
+}
+
+/// Точки трассы идут с шагом в секунду начиная с start_time, а углы наблюдения начиная
+/// со следующей секунды, поэтому строки объединяются по времени и пропуски остаются пустыми
+pub fn render_trajectory(
+    start_time: DateTime<Utc>,
+    trajectory: &[SerializableGeodedic],
+    look_angles: &[SerializableBearing],
+    delimiter: Delimiter,
+) -> String {
+    let mut output = String::new();
+
+    delimiter.push_row(&mut output, &to_fields(&[
+        "time", "lon", "lat", "alt",
+        "az", "el", "range", "range_rate", "magnitude", "downlink_hz", "uplink_hz",
+    ]));
+
+    let rows_count = usize::max(trajectory.len(), look_angles.len() + 1);
+
+    for row in 0..rows_count {
+        let time = start_time + Duration::seconds(row as i64);
+
+        let mut fields = vec![format_time(time)];
+
+        match trajectory.get(row) {
+            Some(point) => fields.extend([
+                point.lon.to_string(),
+                point.lat.to_string(),
+                point.alt.to_string(),
+            ]),
+            None => fields.extend(vec![String::new(); 3]),
+        }
+
+        match row.checked_sub(1).and_then(|index| look_angles.get(index)) {
+            Some(bearing) => fields.extend([
+                bearing.az.to_string(),
+                bearing.el.to_string(),
+                bearing.range.to_string(),
+                bearing.range_rate.to_string(),
+                format_optional(bearing.magnitude),
+                format_optional(bearing.downlink_hz),
+                format_optional(bearing.uplink_hz),
+            ]),
+            None => fields.extend(vec![String::new(); 7]),
+        }
+
+        delimiter.push_row(&mut output, &fields);
+    }
+
+    output
+}
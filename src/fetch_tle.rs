@@ -1,21 +1,202 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use chrono::{DateTime, Utc};
+use reqwest::header::{CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RETRY_AFTER};
+use reqwest::StatusCode;
 use serde::{Serialize, Deserialize};
-use std::io::Read;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::Duration;
+
+use super::catalog::{Catalog, CatalogStore};
+use super::omm::{self, OmmError, SourceFormat};
+use super::tle_history::TleHistory;
+use super::tle_parser::{self, ParsedTle, RecordError, TleRecord};
 
 #[derive(Debug, thiserror::Error)]
 pub enum TleFetchingError {
-    #[error("Tle request failed with error")]
+    #[error("Tle request failed: {0}")]
     RequestError(#[from] reqwest::Error),
     #[error("Failed to save tle file")]
     FileSavingError(#[from] tokio::io::Error),
+    #[error("Failed to parse OMM")]
+    OmmParsingError(#[from] OmmError),
+    #[error("Source {url} responded with status {status}")]
+    UnexpectedStatus { url: String, status: StatusCode },
+    #[error("Source {url} responded with content type {content_type:?}, which doesn't match its format")]
+    UnexpectedContentType { url: String, content_type: String },
+    #[error("Only {count} element sets were downloaded, at least {min} are expected")]
+    TooFewRecords { count: usize, min: usize },
+    #[error("Source {url} returned {count} element sets instead of {previous}, which is more than allowed to disappear")]
+    SourceShrunk { url: String, count: usize, previous: usize },
+    #[error("Source {url} responded with status {status}, requests are paused to avoid a ban")]
+    RateLimited { url: String, status: StatusCode, retry_after_seconds: Option<u64> },
+    #[error("None of the tle sources could be fetched")]
+    AllSourcesFailed,
+}
+
+impl TleFetchingError {
+    /// Сетевые сбои и ошибки сервера могут пройти при повторном запросе
+    fn is_transient(&self) -> bool {
+        match self {
+            TleFetchingError::RequestError(_) => true,
+            TleFetchingError::UnexpectedStatus { status, .. } => status.is_server_error(),
+            _ => false,
+        }
+    }
+}
+
+/// Источник элементов орбит: просто адрес с tle или адрес с указанием формата
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum TleSource {
+    Url(String),
+    WithFormat { url: String, format: SourceFormat },
+}
+
+impl TleSource {
+    pub fn url(&self) -> &str {
+        match self {
+            TleSource::Url(url) | TleSource::WithFormat { url, .. } => url,
+        }
+    }
+
+    pub fn format(&self) -> SourceFormat {
+        match self {
+            TleSource::Url(_) => SourceFormat::Tle,
+            TleSource::WithFormat { format, .. } => *format,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct FetchingSettings {
-    pub tle_urls: Vec<String>,
+    pub tle_urls: Vec<TleSource>,
     pub delay_seconds: u64,
     pub do_track_everything: bool,
     pub satellites_to_track: Vec<String>,
+    /// Меньшее число наборов элементов во всех источниках считается сбоем загрузки
+    #[serde(default = "default_min_records_count")]
+    pub min_records_count: usize,
+    /// Наибольшая доля наборов элементов источника, которая может пропасть за одну загрузку
+    #[serde(default = "default_max_shrink_ratio")]
+    pub max_shrink_ratio: f64,
+    /// Время ожидания ответа одного источника
+    #[serde(default = "default_request_timeout_seconds")]
+    pub request_timeout_seconds: u64,
+    /// Сколько раз повторять неудавшийся запрос, каждый раз удваивая паузу
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    #[serde(default = "default_retry_delay_seconds")]
+    pub retry_delay_seconds: u64,
+}
+
+/// Предел паузы для источника, ответившего 403 или 429
+const MAX_RATE_LIMIT_BACKOFF_SECONDS: u64 = 24 * 60 * 60;
+
+fn default_min_records_count() -> usize {
+    1
+}
+
+fn default_max_shrink_ratio() -> f64 {
+    0.5
+}
+
+fn default_request_timeout_seconds() -> u64 {
+    60
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_retry_delay_seconds() -> u64 {
+    10
+}
+
+/// Состояние одного источника по всем загрузкам с момента запуска
+#[derive(Serialize, Clone, Default)]
+pub struct SourceReport {
+    pub url: String,
+    pub last_attempt_time: Option<DateTime<Utc>>,
+    pub last_success_time: Option<DateTime<Utc>>,
+    pub last_error_time: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    /// До этого времени источник не запрашивается после ответа 403 или 429
+    pub blocked_until: Option<DateTime<Utc>>,
+    /// Число наборов элементов при последней успешной загрузке
+    pub records_count: usize,
+    /// Наборы элементов, не прошедшие проверку при последней успешной загрузке
+    pub errors: Vec<RecordError>,
+}
+
+/// Отчет о загрузках tle по источникам, который отдается через api
+#[derive(Default)]
+pub struct FetchReportStore {
+    sources: RwLock<Vec<SourceReport>>,
+}
+
+impl FetchReportStore {
+    pub fn snapshot(&self) -> Vec<SourceReport> {
+        self.sources.read()
+            .expect("Fetch report lock shouldn't be poisoned")
+            .clone()
+    }
+
+    fn update(&self, url: &str, update: impl FnOnce(&mut SourceReport)) {
+        let mut sources = self.sources.write().expect("Fetch report lock shouldn't be poisoned");
+
+        match sources.iter_mut().find(|report| report.url == url) {
+            Some(report) => update(report),
+            None => {
+                let mut report = SourceReport { url: url.to_string(), ..Default::default() };
+                update(&mut report);
+                sources.push(report);
+            }
+        }
+    }
+}
+
+/// Последний удачный ответ источника: по нему делаются условные запросы, а при ответе 304 берутся наборы элементов.
+/// Кеш сохраняется на диск, чтобы после перезапуска спутники недоступного источника не пропали,
+/// а условные запросы и паузы после 403 или 429 продолжили действовать
+#[derive(Serialize, Deserialize, Default)]
+pub struct SourceCache {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    records: Vec<TleRecord>,
+    /// Число ответов 403 или 429 подряд
+    rate_limited_count: u32,
+    blocked_until: Option<DateTime<Utc>>,
+}
+
+/// Кеши источников по адресу
+#[derive(Serialize, Deserialize, Default)]
+#[serde(transparent)]
+pub struct SourceCaches {
+    sources: HashMap<String, SourceCache>,
+    /// Каталог уже собран из этих кешей; сразу после запуска его нужно пересобрать, даже если источники не изменились
+    #[serde(skip)]
+    is_applied: bool,
+}
+
+impl SourceCache {
+    /// Пауза растет вдвое с каждым ответом 403 или 429 подряд, но не меньше, чем просит сервер
+    fn block(&mut self, settings: &FetchingSettings, now: DateTime<Utc>, retry_after_seconds: Option<u64>) {
+        let backoff_seconds = settings.delay_seconds
+            .saturating_mul(2u64.saturating_pow(self.rate_limited_count))
+            .min(MAX_RATE_LIMIT_BACKOFF_SECONDS);
+        let delay_seconds = backoff_seconds.max(retry_after_seconds.unwrap_or(0));
+
+        self.rate_limited_count += 1;
+        self.blocked_until = Some(now + chrono::Duration::seconds(delay_seconds as i64));
+    }
+}
+
+enum SourceResponse {
+    Modified { parsed: ParsedTle, etag: Option<String>, last_modified: Option<String> },
+    /// Ответ 304: данные не изменились с прошлой загрузки
+    NotModified,
 }
 
 pub async fn read_settings() -> FetchingSettings {
@@ -32,18 +213,37 @@ pub async fn read_settings() -> FetchingSettings {
     serde_json::from_str(&content).expect("Json should be well-formatted")
 }
 
-pub fn read_settings_sync() -> FetchingSettings {
-    let path = std::env::var("TLE_FETCHING_SETTINGS_PATH")
-        .expect("TLE_FETCHING_SETTINGS_PATH env variable should be set");
+/// Кеши источников с прошлого запуска; без файла или с поврежденным файлом загрузка начинается с чистого листа
+pub async fn read_source_caches() -> SourceCaches {
+    let path = std::env::var("TLE_SOURCE_CACHES_PATH")
+        .expect("TLE_SOURCE_CACHES_PATH env variable should be set");
+
+    let content = match tokio::fs::read_to_string(&path).await {
+        Ok(content) => content,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return SourceCaches::default(),
+        Err(error) => {
+            log::warn!("Failed to read tle source caches, starting without them: {:?}", error);
+            return SourceCaches::default();
+        }
+    };
 
-    let mut file = std::fs::File::open(path)
-        .expect("Tle fetching settings should be created");
+    serde_json::from_str(&content).unwrap_or_else(|error| {
+        log::warn!("Tle source caches file is malformed, starting without it: {:?}", error);
+        SourceCaches::default()
+    })
+}
 
-    let mut content = String::new();
-    file.read_to_string(&mut content)
-        .expect("Tle fetching settings file shouldn't be corrupted");
+async fn write_source_caches(source_caches: &SourceCaches) -> std::io::Result<()> {
+    let json = serde_json::to_string(source_caches)
+        .expect("Serializing of source caches shouldn't fail");
 
-    serde_json::from_str(&content).expect("Json should be well-formatted")
+    let path = std::env::var("TLE_SOURCE_CACHES_PATH")
+        .expect("TLE_SOURCE_CACHES_PATH env variable should be set");
+
+    let temporary_file_path = format!("{}.tmp", path);
+
+    tokio::fs::write(&temporary_file_path, json).await?;
+    tokio::fs::rename(&temporary_file_path, &path).await
 }
 
 async fn write_settings(settings: &FetchingSettings) -> std::io::Result<()> {
@@ -68,50 +268,244 @@ async fn write_settings(settings: &FetchingSettings) -> std::io::Result<()> {
     Ok(())
 }
 
-pub async fn fetch_tle(settings: &mut FetchingSettings) -> Result<(), TleFetchingError> {
-    let mut all_tle = String::new();
+fn get_header(response: &reqwest::Response, name: reqwest::header::HeaderName) -> Option<String> {
+    response.headers().get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+async fn fetch_source(
+    client: &reqwest::Client, source: &TleSource, cache: &SourceCache,
+) -> Result<SourceResponse, TleFetchingError> {
+    let mut request = client.get(source.url());
 
-    for url in &settings.tle_urls {
-        let response_text = reqwest::get(url).await?.text().await?;
-        all_tle.push_str(&format!("{}\n", response_text));
+    // Без сохраненных наборов элементов ответ 304 нечем было бы заменить
+    if !cache.records.is_empty() {
+        if let Some(etag) = &cache.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+
+        if let Some(last_modified) = &cache.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
     }
 
-    let mut filtered_tle = String::new();
+    let response = request.send().await?;
+
+    match response.status() {
+        StatusCode::NOT_MODIFIED if !cache.records.is_empty() => return Ok(SourceResponse::NotModified),
+        StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS => return Err(TleFetchingError::RateLimited {
+            url: source.url().to_string(),
+            status: response.status(),
+            retry_after_seconds: get_header(&response, RETRY_AFTER)
+                .and_then(|retry_after| retry_after.trim().parse().ok()),
+        }),
+        status if !status.is_success() => return Err(TleFetchingError::UnexpectedStatus {
+            url: source.url().to_string(),
+            status,
+        }),
+        _ => {}
+    }
 
-    if settings.do_track_everything {
-        // Добавление списка спутников в файл с настройками, чтобы иметь возможность
-        // получить список всех спутников в дальнейшем
+    if let Some(content_type) = get_header(&response, CONTENT_TYPE) {
+        let content_type = content_type.to_lowercase();
+
+        if !source.format().accepts_content_type(&content_type) {
+            return Err(TleFetchingError::UnexpectedContentType { url: source.url().to_string(), content_type });
+        }
+    }
+
+    let etag = get_header(&response, ETAG);
+    let last_modified = get_header(&response, LAST_MODIFIED);
+
+    let response_text = response.text().await?;
+    let tle = omm::normalize_to_tle(&response_text, source.format())?;
+
+    Ok(SourceResponse::Modified { parsed: tle_parser::parse(&tle), etag, last_modified })
+}
 
-        let lines: Vec<&str> = all_tle.lines().collect();
-        let mut satellite_names = vec![];
+/// Источник, вернувший намного меньше наборов элементов, чем в прошлый раз, скорее всего отдал обрезанный ответ.
+/// Сравнивается весь ответ источника, до отбора спутников из satellites_to_track
+fn check_source_shrink(
+    response: SourceResponse, source: &TleSource, cache: &SourceCache, settings: &FetchingSettings,
+) -> Result<SourceResponse, TleFetchingError> {
+    let previous = cache.records.len();
+
+    match &response {
+        SourceResponse::Modified { parsed, .. }
+            if (parsed.records.len() as f64) < previous as f64 * (1.0 - settings.max_shrink_ratio) => {
+            Err(TleFetchingError::SourceShrunk { url: source.url().to_string(), count: parsed.records.len(), previous })
+        }
+        _ => Ok(response),
+    }
+}
 
-        for i in (0..lines.len()).step_by(3) {
-            let satellite_name = lines[i].trim().to_string();
+/// Повторяет запрос при временных сбоях с экспоненциально растущей паузой
+async fn fetch_source_with_retries(
+    client: &reqwest::Client, source: &TleSource, cache: &SourceCache, settings: &FetchingSettings,
+) -> Result<SourceResponse, TleFetchingError> {
+    let mut attempt = 0;
 
-            if !satellite_name.is_empty() {
-                satellite_names.push(satellite_name);
+    loop {
+        match fetch_source(client, source, cache).await {
+            Err(error) if error.is_transient() && attempt < settings.max_retries => {
+                let delay_seconds = settings.retry_delay_seconds.saturating_mul(2u64.saturating_pow(attempt));
+                log::warn!("Failed to fetch {}: {}; retrying in {} s", source.url(), error, delay_seconds);
+
+                tokio::time::sleep(Duration::from_secs(delay_seconds)).await;
+                attempt += 1;
             }
+            result => return result,
         }
+    }
+}
 
-        settings.satellites_to_track = satellite_names;
-        write_settings(settings).await?;
+/// После неудачной загрузки следующая начинается раньше обычного, с удвоением паузы до delay_seconds
+pub fn get_next_fetch_delay(settings: &FetchingSettings, consecutive_failures: u32) -> Duration {
+    if consecutive_failures == 0 {
+        return Duration::from_secs(settings.delay_seconds);
+    }
+
+    let delay_seconds = settings.retry_delay_seconds.saturating_mul(2u64.saturating_pow(consecutive_failures));
+
+    Duration::from_secs(delay_seconds.min(settings.delay_seconds))
+}
+
+/// Источники загружаются независимо: в каталог попадает все, что удалось скачать,
+/// а для неизменившихся и временно заблокированных источников берутся наборы элементов из кеша.
+/// Кеши сохраняются после каждой загрузки, даже неудачной, чтобы не потерять паузы после 403 или 429
+pub async fn fetch_tle(
+    settings: &mut FetchingSettings, source_caches: &mut SourceCaches, catalog: &CatalogStore,
+    tle_history: &TleHistory, fetch_reports: &FetchReportStore,
+) -> Result<(), TleFetchingError> {
+    let result = update_catalog(settings, source_caches, catalog, tle_history, fetch_reports).await;
+
+    if let Err(error) = write_source_caches(source_caches).await {
+        log::warn!("Failed to save tle source caches: {:?}", error);
+    }
+
+    result
+}
+
+async fn update_catalog(
+    settings: &mut FetchingSettings, source_caches: &mut SourceCaches, catalog: &CatalogStore,
+    tle_history: &TleHistory, fetch_reports: &FetchReportStore,
+) -> Result<(), TleFetchingError> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(settings.request_timeout_seconds))
+        .build()?;
+
+    let mut records = vec![];
+    let mut fetched_sources_count = 0;
+    // Новые ответы попадают в кеш только вместе с каталогом, иначе следующий запрос получит 304
+    // и данные, которые так и не были применены, потеряются
+    let mut staged_caches = vec![];
+
+    for source in &settings.tle_urls {
+        let attempt_time = Utc::now();
+        let cache = source_caches.sources.entry(source.url().to_string()).or_default();
+
+        if let Some(blocked_until) = cache.blocked_until.filter(|&blocked_until| blocked_until > attempt_time) {
+            log::info!("Skipping {} until {} after it limited our requests", source.url(), blocked_until);
+
+            records.extend(cache.records.iter().cloned());
+            continue;
+        }
+
+        let response = fetch_source_with_retries(&client, source, cache, settings).await
+            .and_then(|response| check_source_shrink(response, source, cache, settings));
 
-        filtered_tle = all_tle;
-    } else {
-        let lines: Vec<&str> = all_tle.lines().collect();
+        match response {
+            Ok(SourceResponse::NotModified) => {
+                log::info!("{} hasn't changed since the last fetch", source.url());
 
-        for i in (0..lines.len()).step_by(3) {
-            let satellite_name = lines[i].trim().to_string();
+                fetch_reports.update(source.url(), |report| {
+                    report.last_attempt_time = Some(attempt_time);
+                    report.last_success_time = Some(Utc::now());
+                    report.blocked_until = None;
+                });
 
-            if !satellite_name.is_empty() &&
-                settings.satellites_to_track.contains(&satellite_name) {
-                for shift in 0..=2 {
-                    filtered_tle.push_str(&format!("{}\n", lines[i + shift]));
+                cache.rate_limited_count = 0;
+                cache.blocked_until = None;
+
+                records.extend(cache.records.iter().cloned());
+                fetched_sources_count += 1;
+            }
+            Ok(SourceResponse::Modified {
+                parsed: ParsedTle { records: source_records, errors },
+                etag,
+                last_modified,
+            }) => {
+                for error in &errors {
+                    log::warn!("Skipping element set at line {} of {}: {}", error.line, source.url(), error.message);
                 }
+
+                fetch_reports.update(source.url(), |report| {
+                    report.last_attempt_time = Some(attempt_time);
+                    report.last_success_time = Some(Utc::now());
+                    report.blocked_until = None;
+                    report.records_count = source_records.len();
+                    report.errors = errors;
+                });
+
+                cache.rate_limited_count = 0;
+                cache.blocked_until = None;
+
+                records.extend(source_records.iter().cloned());
+                fetched_sources_count += 1;
+
+                staged_caches.push((
+                    source.url().to_string(),
+                    SourceCache { etag, last_modified, records: source_records, ..Default::default() },
+                ));
+            }
+            Err(error) => {
+                log::warn!("Failed to fetch tle from {}: {}", source.url(), error);
+
+                if let TleFetchingError::RateLimited { retry_after_seconds, .. } = &error {
+                    cache.block(settings, attempt_time, *retry_after_seconds);
+                }
+
+                records.extend(cache.records.iter().cloned());
+
+                fetch_reports.update(source.url(), |report| {
+                    report.last_attempt_time = Some(attempt_time);
+                    report.last_error_time = Some(Utc::now());
+                    report.last_error = Some(error.to_string());
+                    report.blocked_until = cache.blocked_until;
+                });
             }
         }
     }
 
+    // Кешированные наборы элементов только дополняют загрузку, но не заменяют ее
+    if fetched_sources_count == 0 {
+        return Err(TleFetchingError::AllSourcesFailed);
+    }
+
+    if staged_caches.is_empty() && source_caches.is_applied {
+        log::info!("Tle sources haven't changed, keeping the current catalog");
+        return Ok(());
+    }
+
+    // Проверки до записи файлов: если загрузка выглядит сбойной, остается последний хороший каталог
+    if records.len() < settings.min_records_count {
+        return Err(TleFetchingError::TooFewRecords { count: records.len(), min: settings.min_records_count });
+    }
+
+    if !settings.do_track_everything {
+        records.retain(|record| settings.satellites_to_track.contains(&record.display_name()));
+    }
+
+    if settings.do_track_everything {
+        // Добавление списка спутников в файл с настройками, чтобы иметь возможность
+        // получить список всех спутников в дальнейшем
+        settings.satellites_to_track = records.iter().map(TleRecord::display_name).collect();
+        write_settings(settings).await?;
+    }
+
+    let filtered_tle: String = records.iter().map(TleRecord::to_3le).collect();
+
     let tle_file_path = std::env::var("TLE_FILE_PATH")
         .expect("TLE_FILE_PATH env variable should be set");
 
@@ -126,5 +520,18 @@ pub async fn fetch_tle(settings: &mut FetchingSettings) -> Result<(), TleFetchin
         tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
     }
 
+    match tle_history.append(&filtered_tle).await {
+        Ok(appended) => log::info!("Tle history updated, {} new element sets saved", appended),
+        Err(error) => log::warn!("Failed to update tle history: {:?}", error),
+    }
+
+    let new_catalog = Catalog::parse(&filtered_tle);
+    log::info!("Catalog updated, {} satellites loaded", new_catalog.satellites_count());
+    catalog.replace(new_catalog);
+
+    source_caches.sources.extend(staged_caches);
+    source_caches.sources.retain(|url, _| settings.tle_urls.iter().any(|source| source.url() == url));
+    source_caches.is_applied = true;
+
     Ok(())
 }
\ No newline at end of file
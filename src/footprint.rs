@@ -0,0 +1,213 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+
+use super::calculations::EARTH_RADIUS_KM;
+
+/// Число вершин окружности зоны видимости
+const FOOTPRINT_POINTS: usize = 120;
+
+/// Центральный угол (радианы) зоны, из которой спутник на высоте altitude_km виден выше min_elevation
+pub fn get_footprint_angle(altitude_km: f64, min_elevation: f64) -> f64 {
+    let min_elevation = min_elevation.to_radians();
+
+    (EARTH_RADIUS_KM * min_elevation.cos() / (EARTH_RADIUS_KM + altitude_km)).acos() - min_elevation
+}
+
+/// Точка на расстоянии angle (радианы) от центра по азимуту bearing, долгота не нормализуется
+fn destination(center_lon: f64, center_lat: f64, angle: f64, bearing: f64) -> [f64; 2] {
+    let center_lat = center_lat.to_radians();
+
+    let lat = (center_lat.sin() * angle.cos() + center_lat.cos() * angle.sin() * bearing.cos()).asin();
+    let delta_lon = (bearing.sin() * angle.sin() * center_lat.cos())
+        .atan2(angle.cos() - center_lat.sin() * lat.sin());
+
+    [center_lon + delta_lon.to_degrees(), lat.to_degrees()]
+}
+
+/// Отсекает кольцо полуплоскостью lon <= boundary (или lon >= boundary) по Сазерленду-Ходжману
+fn clip_ring(ring: &[[f64; 2]], boundary: f64, keep_west: bool) -> Vec<[f64; 2]> {
+    let inside = |point: &[f64; 2]| if keep_west { point[0] <= boundary } else { point[0] >= boundary };
+    let intersect = |a: &[f64; 2], b: &[f64; 2]| {
+        let fraction = (boundary - a[0]) / (b[0] - a[0]);
+        [boundary, a[1] + (b[1] - a[1]) * fraction]
+    };
+
+    let mut result = vec![];
+
+    for (index, current) in ring.iter().enumerate() {
+        let previous = &ring[(index + ring.len() - 1) % ring.len()];
+
+        match (inside(previous), inside(current)) {
+            (true, true) => result.push(*current),
+            (true, false) => result.push(intersect(previous, current)),
+            (false, true) => {
+                result.push(intersect(previous, current));
+                result.push(*current);
+            }
+            (false, false) => {}
+        }
+    }
+
+    result
+}
+
+fn close_ring(mut ring: Vec<[f64; 2]>) -> Vec<[f64; 2]> {
+    if let Some(&first) = ring.first() {
+        ring.push(first);
+    }
+    ring
+}
+
+/// Зона, накрывающая полюс: граница однозначна по долготе, поэтому кольцо идет вдоль нее
+/// от -180° до 180° и замыкается через полюс
+fn polar_cap_geometry(boundary: Vec<[f64; 2]>, is_north: bool) -> Value {
+    let mut points: Vec<[f64; 2]> = boundary.into_iter()
+        .map(|[lon, lat]| [(lon + 180.0).rem_euclid(360.0) - 180.0, lat])
+        .collect();
+    points.sort_by(|a, b| a[0].total_cmp(&b[0]));
+
+    let first = points[0];
+    let last = points[points.len() - 1];
+    let edge_lat = last[1] + (first[1] - last[1]) * (180.0 - last[0]) / (first[0] + 360.0 - last[0]);
+    let pole_lat = if is_north { 90.0 } else { -90.0 };
+
+    let mut ring = vec![[-180.0, edge_lat]];
+    ring.extend(points);
+    ring.extend([[180.0, edge_lat], [180.0, pole_lat], [-180.0, pole_lat]]);
+
+    // Для южного полюса такой обход идет по часовой стрелке, а GeoJSON требует против
+    if !is_north {
+        ring.reverse();
+    }
+
+    json!({"type": "Polygon", "coordinates": [close_ring(ring)]})
+}
+
+/// Полигон зоны видимости; у полюсов замыкается через полюс, на антимеридиане
+/// разрезается на MultiPolygon
+pub fn get_footprint_geometry(lon: f64, lat: f64, altitude_km: f64, min_elevation: f64) -> Value {
+    let angle = get_footprint_angle(altitude_km, min_elevation);
+
+    // Обход против часовой стрелки, как требует RFC 7946
+    let ring: Vec<[f64; 2]> = (0..FOOTPRINT_POINTS)
+        .map(|index| {
+            let bearing = -(index as f64) * std::f64::consts::TAU / FOOTPRINT_POINTS as f64;
+            destination(lon, lat, angle, bearing)
+        })
+        .collect();
+
+    if angle >= std::f64::consts::FRAC_PI_2 - lat.abs().to_radians() {
+        return polar_cap_geometry(ring, lat >= 0.0);
+    }
+
+    let max_lon = ring.iter().map(|point| point[0]).fold(f64::NEG_INFINITY, f64::max);
+    let min_lon = ring.iter().map(|point| point[0]).fold(f64::INFINITY, f64::min);
+
+    let (boundary, shift) = if max_lon > 180.0 {
+        (180.0, -360.0)
+    } else if min_lon < -180.0 {
+        (-180.0, 360.0)
+    } else {
+        return json!({"type": "Polygon", "coordinates": [close_ring(ring)]});
+    };
+
+    let main_part = clip_ring(&ring, boundary, boundary > 0.0);
+    let wrapped_part: Vec<[f64; 2]> = clip_ring(&ring, boundary, boundary < 0.0).into_iter()
+        .map(|[lon, lat]| [lon + shift, lat])
+        .collect();
+
+    json!({
+        "type": "MultiPolygon",
+        "coordinates": [[close_ring(main_part)], [close_ring(wrapped_part)]],
+    })
+}
+
+pub fn get_footprint_feature(time: DateTime<Utc>, position: &satellite::Geodedic, min_elevation: f64) -> Value {
+    let angle = get_footprint_angle(position.height, min_elevation);
+
+    json!({
+        "type": "Feature",
+        "geometry": get_footprint_geometry(position.longitude, position.latitude, position.height, min_elevation),
+        "properties": {
+            "time": time.to_rfc3339_opts(SecondsFormat::Millis, true),
+            "lon": position.longitude,
+            "lat": position.latitude,
+            "alt": position.height,
+            "min_elevation": min_elevation,
+            "radius_km": angle * EARTH_RADIUS_KM,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ISS_ALTITUDE_KM: f64 = 420.0;
+
+    fn rings(geometry: &Value) -> Vec<Vec<[f64; 2]>> {
+        let polygons = match geometry["type"].as_str() {
+            Some("Polygon") => vec![geometry["coordinates"].clone()],
+            Some("MultiPolygon") => geometry["coordinates"].as_array().unwrap().clone(),
+            other => panic!("Unexpected geometry type {:?}", other),
+        };
+
+        polygons.iter()
+            .map(|polygon| serde_json::from_value(polygon[0].clone()).expect("Ring should be a list of points"))
+            .collect()
+    }
+
+    /// Удвоенная площадь по формуле шнурования, положительная при обходе против часовой стрелки
+    fn signed_area(ring: &[[f64; 2]]) -> f64 {
+        ring.windows(2).map(|pair| pair[0][0] * pair[1][1] - pair[1][0] * pair[0][1]).sum()
+    }
+
+    fn assert_valid_ring(ring: &[[f64; 2]]) {
+        assert_eq!(ring.first(), ring.last(), "Ring should be closed");
+        assert!(ring.iter().all(|&[lon, lat]| (-180.0..=180.0).contains(&lon) && (-90.0..=90.0).contains(&lat)));
+        assert!(signed_area(ring) > 0.0, "Ring should go counterclockwise");
+    }
+
+    #[test]
+    fn footprint_near_north_pole_is_closed_through_the_pole() {
+        let angle = get_footprint_angle(ISS_ALTITUDE_KM, 0.0).to_degrees();
+        let geometry = get_footprint_geometry(30.0, 85.0, ISS_ALTITUDE_KM, 0.0);
+
+        assert_eq!(geometry["type"], "Polygon");
+        let ring = &rings(&geometry)[0];
+        assert_valid_ring(ring);
+        assert!(ring.contains(&[180.0, 90.0]) && ring.contains(&[-180.0, 90.0]));
+
+        // Ближе всего к экватору зона под спутником, а за полюсом ее граница на 5° выше
+        let boundary: Vec<[f64; 2]> = ring.iter().copied().filter(|point| point[1] < 90.0).collect();
+        let lowest = boundary.iter().min_by(|a, b| a[1].total_cmp(&b[1])).unwrap();
+        let highest = boundary.iter().max_by(|a, b| a[1].total_cmp(&b[1])).unwrap();
+
+        assert!((lowest[1] - (85.0 - angle)).abs() < 0.1, "{:?}", lowest);
+        assert!((lowest[0] - 30.0).abs() < 3.1, "{:?}", lowest);
+        assert!((highest[1] - (95.0 - angle)).abs() < 0.1, "{:?}", highest);
+    }
+
+    #[test]
+    fn footprint_near_south_pole_goes_counterclockwise() {
+        let geometry = get_footprint_geometry(-120.0, -88.0, ISS_ALTITUDE_KM, 10.0);
+
+        assert_eq!(geometry["type"], "Polygon");
+        let ring = &rings(&geometry)[0];
+        assert_valid_ring(ring);
+        assert!(ring.contains(&[180.0, -90.0]) && ring.contains(&[-180.0, -90.0]));
+    }
+
+    #[test]
+    fn footprint_on_antimeridian_is_split() {
+        let geometry = get_footprint_geometry(179.0, 0.0, ISS_ALTITUDE_KM, 0.0);
+
+        assert_eq!(geometry["type"], "MultiPolygon");
+
+        let rings = rings(&geometry);
+        assert_eq!(rings.len(), 2);
+        rings.iter().for_each(|ring| assert_valid_ring(ring));
+        assert!(rings[0].iter().all(|point| point[0] > 0.0));
+        assert!(rings[1].iter().all(|point| point[0] < 0.0));
+    }
+}
@@ -1,9 +1,11 @@
+use std::str::FromStr;
+
 use actix_web::{error, Error, HttpRequest, HttpResponse};
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize};
 use validator::{Validate, ValidationError};
 use chrono::naive::NaiveDateTime;
 
-use super::fetch_tle;
+use super::calculations::Refraction;
 
 pub fn parse_error_handler(err: error::UrlencodedError, _req: &HttpRequest) -> Error {
     error::InternalError::from_response(
@@ -21,62 +23,353 @@ fn validate_datetime(value: &str) -> Result<(), ValidationError> {
     }
 }
 
-fn validate_satellites_list(satellites: &str) -> Result<(), ValidationError> {
-    let valid_satellites = fetch_tle::read_settings_sync().satellites_to_track;
+/// Поля вложенной через #[serde(flatten)] структуры приходят из запроса строками и разбираются вручную
+fn deserialize_flattened<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: FromStr,
+        T::Err: std::fmt::Display,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| value.parse().map_err(de::Error::custom))
+        .transpose()
+}
+
+fn deserialize_flattened_flag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    Ok(deserialize_flattened(deserializer)?.unwrap_or_default())
+}
 
-    if satellites.split(",").all(|item| valid_satellites.contains(&item.to_string())) {
-        Ok(())
-    } else {
-        Err(ValidationError::new("Satellites list validation failed"))
+/// Параметры атмосферной рефракции, общие для форм с углами места
+#[derive(Serialize, Deserialize, Validate, Debug, Default)]
+pub struct RefractionParams {
+    /// Учитывать атмосферную рефракцию в углах места и моментах восхода и захода
+    #[serde(default, deserialize_with = "deserialize_flattened_flag")]
+    pub refraction: bool,
+    /// Давление у наблюдателя для модели рефракции, по умолчанию стандартная атмосфера
+    #[serde(default, deserialize_with = "deserialize_flattened")]
+    #[validate(range(min = 100.0, max = 1100.0, message = "Давление должно быть от 100 до 1100 гПа"))]
+    pub pressure_hpa: Option<f64>,
+    /// Температура у наблюдателя для модели рефракции, по умолчанию стандартная атмосфера
+    #[serde(default, deserialize_with = "deserialize_flattened")]
+    #[validate(range(min = - 90.0, max = 60.0, message = "Температура должна быть от -90 до 60 °C"))]
+    pub temperature_celsius: Option<f64>,
+}
+
+impl RefractionParams {
+    pub fn to_refraction(&self) -> Option<Refraction> {
+        self.refraction.then(|| Refraction::from_conditions(self.pressure_hpa, self.temperature_celsius))
     }
 }
 
 #[derive(Serialize, Deserialize, Validate, Debug)]
 pub struct SatelliteDataForm {
+    /// Имя, номер NORAD или обозначение COSPAR
     #[validate(length(min = 1, max = 32))]
     pub satellite_name: String,
-    #[validate(range(min = - 180.0, max = 180.0, message = "Широта должна быть от -180 до 180"))]
-    pub lat: f64,
-    #[validate(range(min = - 90.0, max = 90.0, message = "Долгота должна быть от -90 до 90"))]
-    pub lon: f64,
+    /// Сохраненная станция, заменяет lat, lon и alt
+    #[validate(length(min = 1, max = 64))]
+    pub station: Option<String>,
+    #[validate(range(min = - 90.0, max = 90.0, message = "Широта должна быть от -90 до 90"))]
+    pub lat: Option<f64>,
+    #[validate(range(min = - 180.0, max = 180.0, message = "Долгота должна быть от -180 до 180"))]
+    pub lon: Option<f64>,
     #[validate(range(min = 0.0, max = 10000.0, message = "Слишком большая высота"))]
-    pub alt: f64,
+    pub alt: Option<f64>,
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub refraction_params: RefractionParams,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PassesSorting {
+    #[default]
+    Time,
+    Brightness,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleStrategy {
+    Greedy,
+    #[default]
+    Optimal,
+}
+
+/// Формат ответа; если не указан, выбирается по заголовку Accept
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    Json,
+    Ics,
+    Csv,
+    Tsv,
+    Geojson,
+    Kml,
 }
 
 #[derive(Serialize, Deserialize, Validate, Debug)]
 pub struct PassesListForm {
-    #[validate(custom(function = "validate_satellites_list"))]
+    /// Имена, номера NORAD или обозначения COSPAR через запятую
+    #[validate(length(min = 1, max = 4096))]
     pub satellites: String,
-    #[validate(range(min = - 180.0, max = 180.0, message = "Широта должна быть от -180 до 180"))]
-    pub lat: f64,
-    #[validate(range(min = - 90.0, max = 90.0, message = "Долгота должна быть от -90 до 90"))]
-    pub lon: f64,
+    /// Сохраненная станция, заменяет lat, lon и alt
+    #[validate(length(min = 1, max = 64))]
+    pub station: Option<String>,
+    #[validate(range(min = - 90.0, max = 90.0, message = "Широта должна быть от -90 до 90"))]
+    pub lat: Option<f64>,
+    #[validate(range(min = - 180.0, max = 180.0, message = "Долгота должна быть от -180 до 180"))]
+    pub lon: Option<f64>,
     #[validate(range(min = 0.0, max = 10000.0, message = "Слишком большая высота"))]
-    pub alt: f64,
+    pub alt: Option<f64>,
+    /// По умолчанию минимальный угол места станции или 0
     #[validate(range(min = 0.0, max = 90.0, message = "Элевация должна быть от 0 до 90"))]
-    pub min_elevation: f64,
+    pub min_elevation: Option<f64>,
     #[validate(range(min = 0.0, max = 90.0, message = "Кульминация должна быть от 0 до 90"))]
     pub min_apogee: f64,
     #[validate(custom(function = "validate_datetime", message = "Неверный формат времени"))]
     pub start_time: String,
     #[validate(range(min = 1, max = 240, message = "Длительность наблюдения не должна превышать 10 дней"))]
     pub duration: u8,
+    /// Оставить только пролеты, во время которых спутник освещен, а у наблюдателя темно
+    #[serde(default)]
+    pub visible_only: bool,
+    /// Оставить только пролеты не тусклее этой звездной величины
+    #[validate(range(min = - 30.0, max = 30.0, message = "Звездная величина должна быть от -30 до 30"))]
+    pub max_magnitude: Option<f64>,
+    #[serde(default)]
+    pub sort_by: PassesSorting,
+    /// Имя сохраненной маски горизонта, по которой считаются восход и заход
+    #[validate(length(min = 1, max = 64))]
+    pub horizon_mask: Option<String>,
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub refraction_params: RefractionParams,
+    pub format: Option<OutputFormat>,
 }
 
 
 // TODO: сделать так чтобы старт тайм и энд тайм были оциаональными параметрами и если их нет, то по дефолту как в форме сверху
 #[derive(Serialize, Deserialize, Validate, Debug)]
 pub struct TrajectoryForm {
-    #[validate(custom(function = "validate_satellites_list"))]
+    /// Имя, номер NORAD или обозначение COSPAR
+    #[validate(length(min = 1, max = 32))]
     pub satellite: String,
-    #[validate(range(min = - 180.0, max = 180.0, message = "Широта должна быть от -180 до 180"))]
+    /// Сохраненная станция, заменяет lat, lon и alt
+    #[validate(length(min = 1, max = 64))]
+    pub station: Option<String>,
+    #[validate(range(min = - 90.0, max = 90.0, message = "Широта должна быть от -90 до 90"))]
+    pub lat: Option<f64>,
+    #[validate(range(min = - 180.0, max = 180.0, message = "Долгота должна быть от -180 до 180"))]
+    pub lon: Option<f64>,
+    #[validate(range(min = 0.0, max = 10000.0, message = "Слишком большая высота"))]
+    pub alt: Option<f64>,
+    #[validate(custom(function = "validate_datetime", message = "Неверный формат времени"))]
+    pub start_time: String,
+    #[validate(custom(function = "validate_datetime", message = "Неверный формат времени"))]
+    pub end_time: String,
+    /// Частота передатчика спутника, для которой считается доплеровская поправка
+    #[validate(range(min = 0.0, max = 3e11, message = "Частота должна быть от 0 до 300 ГГц"))]
+    pub downlink_hz: Option<f64>,
+    /// Частота приемника спутника, для которой считается доплеровская поправка
+    #[validate(range(min = 0.0, max = 3e11, message = "Частота должна быть от 0 до 300 ГГц"))]
+    pub uplink_hz: Option<f64>,
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub refraction_params: RefractionParams,
+    pub format: Option<OutputFormat>,
+}
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+pub struct CzmlForm {
+    /// Имена, номера NORAD или обозначения COSPAR через запятую
+    #[validate(length(min = 1, max = 4096))]
+    pub satellites: String,
+    #[validate(range(min = - 90.0, max = 90.0, message = "Широта должна быть от -90 до 90"))]
     pub lat: f64,
-    #[validate(range(min = - 90.0, max = 90.0, message = "Долгота должна быть от -90 до 90"))]
+    #[validate(range(min = - 180.0, max = 180.0, message = "Долгота должна быть от -180 до 180"))]
     pub lon: f64,
     #[validate(range(min = 0.0, max = 10000.0, message = "Слишком большая высота"))]
     pub alt: f64,
+    #[validate(range(min = 0.0, max = 90.0, message = "Элевация должна быть от 0 до 90"))]
+    pub min_elevation: f64,
     #[validate(custom(function = "validate_datetime", message = "Неверный формат времени"))]
     pub start_time: String,
     #[validate(custom(function = "validate_datetime", message = "Неверный формат времени"))]
     pub end_time: String,
+    /// Шаг между точками трассы в документе, в секундах
+    #[validate(range(min = 1, max = 600, message = "Шаг должен быть от 1 до 600 секунд"))]
+    pub step_seconds: Option<u16>,
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub refraction_params: RefractionParams,
+}
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+pub struct FootprintForm {
+    /// Имя, номер NORAD или обозначение COSPAR
+    #[validate(length(min = 1, max = 32))]
+    pub satellite: String,
+    #[validate(range(min = 0.0, max = 90.0, message = "Элевация должна быть от 0 до 90"))]
+    pub min_elevation: f64,
+    #[validate(custom(function = "validate_datetime", message = "Неверный формат времени"))]
+    pub start_time: String,
+    /// Если указано, возвращается серия зон на окне от start_time до end_time
+    #[validate(custom(function = "validate_datetime", message = "Неверный формат времени"))]
+    pub end_time: Option<String>,
+    #[validate(range(min = 1, max = 3600, message = "Шаг должен быть от 1 до 3600 секунд"))]
+    pub step_seconds: Option<u16>,
+}
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+pub struct DemHorizonMaskForm {
+    /// Имя файла карты высот в каталоге DEM без расширения
+    #[validate(length(min = 1, max = 64))]
+    pub dem: String,
+    #[validate(range(min = - 90.0, max = 90.0, message = "Широта должна быть от -90 до 90"))]
+    pub lat: f64,
+    #[validate(range(min = - 180.0, max = 180.0, message = "Долгота должна быть от -180 до 180"))]
+    pub lon: f64,
+    #[validate(range(min = 0.0, max = 10000.0, message = "Слишком большая высота"))]
+    pub alt: f64,
+}
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+pub struct StationForm {
+    #[validate(length(min = 1, max = 128))]
+    pub name: String,
+    #[validate(range(min = - 90.0, max = 90.0, message = "Широта должна быть от -90 до 90"))]
+    pub lat: f64,
+    #[validate(range(min = - 180.0, max = 180.0, message = "Долгота должна быть от -180 до 180"))]
+    pub lon: f64,
+    #[validate(range(min = 0.0, max = 10000.0, message = "Слишком большая высота"))]
+    pub alt: f64,
+    #[validate(range(min = 0.0, max = 90.0, message = "Элевация должна быть от 0 до 90"))]
+    #[serde(default)]
+    pub min_elevation: f64,
+    /// Имя сохраненной маски горизонта
+    #[validate(length(min = 1, max = 64))]
+    pub horizon_mask: Option<String>,
+    #[validate(range(min = 0.0, max = 3e11, message = "Частота должна быть от 0 до 300 ГГц"))]
+    pub downlink_hz: Option<f64>,
+    #[validate(range(min = 0.0, max = 3e11, message = "Частота должна быть от 0 до 300 ГГц"))]
+    pub uplink_hz: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+pub struct NetworkPassesForm {
+    /// Имена, номера NORAD или обозначения COSPAR через запятую
+    #[validate(length(min = 1, max = 4096))]
+    pub satellites: String,
+    /// Идентификаторы сохраненных станций через запятую
+    #[validate(length(min = 1, max = 4096))]
+    pub stations: String,
+    #[validate(range(min = 0.0, max = 90.0, message = "Кульминация должна быть от 0 до 90"))]
+    #[serde(default)]
+    pub min_apogee: f64,
+    #[validate(custom(function = "validate_datetime", message = "Неверный формат времени"))]
+    pub start_time: String,
+    #[validate(range(min = 1, max = 240, message = "Длительность наблюдения не должна превышать 10 дней"))]
+    pub duration: u8,
+}
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+pub struct ContactScheduleForm {
+    /// Имена, номера NORAD или обозначения COSPAR через запятую
+    #[validate(length(min = 1, max = 4096))]
+    pub satellites: String,
+    /// Сохраненная станция, заменяет lat, lon и alt
+    #[validate(length(min = 1, max = 64))]
+    pub station: Option<String>,
+    #[validate(range(min = - 90.0, max = 90.0, message = "Широта должна быть от -90 до 90"))]
+    pub lat: Option<f64>,
+    #[validate(range(min = - 180.0, max = 180.0, message = "Долгота должна быть от -180 до 180"))]
+    pub lon: Option<f64>,
+    #[validate(range(min = 0.0, max = 10000.0, message = "Слишком большая высота"))]
+    pub alt: Option<f64>,
+    /// По умолчанию минимальный угол места станции или 0
+    #[validate(range(min = 0.0, max = 90.0, message = "Элевация должна быть от 0 до 90"))]
+    pub min_elevation: Option<f64>,
+    #[validate(custom(function = "validate_datetime", message = "Неверный формат времени"))]
+    pub start_time: String,
+    #[validate(range(min = 1, max = 240, message = "Длительность наблюдения не должна превышать 10 дней"))]
+    pub duration: u8,
+    /// Приоритеты вида "25544:5,AO-7:2", по умолчанию у всех спутников приоритет 1
+    #[validate(length(max = 4096))]
+    pub priorities: Option<String>,
+    #[validate(range(max = 3600, message = "Минимальный сеанс не должен превышать часа"))]
+    #[serde(default)]
+    pub min_contact_seconds: u32,
+    #[validate(range(max = 3600, message = "Время разворота антенны не должно превышать часа"))]
+    #[serde(default)]
+    pub slew_seconds: u32,
+    #[serde(default)]
+    pub strategy: ScheduleStrategy,
+}
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+pub struct RotatorStartForm {
+    /// Имя, номер NORAD или обозначение COSPAR
+    #[validate(length(min = 1, max = 32))]
+    pub satellite: String,
+    #[validate(range(min = - 90.0, max = 90.0, message = "Широта должна быть от -90 до 90"))]
+    pub lat: f64,
+    #[validate(range(min = - 180.0, max = 180.0, message = "Долгота должна быть от -180 до 180"))]
+    pub lon: f64,
+    #[validate(range(min = 0.0, max = 10000.0, message = "Слишком большая высота"))]
+    pub alt: f64,
+}
+
+#[derive(Serialize, Deserialize, Validate, Debug)]
+pub struct RigStartForm {
+    /// Имя, номер NORAD или обозначение COSPAR
+    #[validate(length(min = 1, max = 32))]
+    pub satellite: String,
+    /// Название ретранслятора из настроек радиостанции
+    #[validate(length(min = 1, max = 64))]
+    pub transponder: String,
+    #[validate(range(min = - 90.0, max = 90.0, message = "Широта должна быть от -90 до 90"))]
+    pub lat: f64,
+    #[validate(range(min = - 180.0, max = 180.0, message = "Долгота должна быть от -180 до 180"))]
+    pub lon: f64,
+    #[validate(range(min = 0.0, max = 10000.0, message = "Слишком большая высота"))]
+    pub alt: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::web;
+
+    use super::*;
+
+    #[test]
+    fn parses_flattened_refraction_params_from_query() {
+        let form = web::Query::<SatelliteDataForm>::from_query(
+            "satellite_name=ISS&lat=55.7&lon=37.6&alt=150&refraction=true&pressure_hpa=950&temperature_celsius=-5.5",
+        ).expect("Query should be parsed");
+
+        assert!(form.refraction_params.refraction);
+        assert_eq!(form.refraction_params.pressure_hpa, Some(950.0));
+        assert_eq!(form.refraction_params.temperature_celsius, Some(-5.5));
+        assert!(form.refraction_params.to_refraction().is_some());
+        assert!(form.validate().is_ok());
+    }
+
+    #[test]
+    fn refraction_is_off_by_default() {
+        let form = web::Query::<SatelliteDataForm>::from_query("satellite_name=ISS&lat=55.7&lon=37.6&alt=150")
+            .expect("Query should be parsed");
+
+        assert!(!form.refraction_params.refraction);
+        assert!(form.refraction_params.to_refraction().is_none());
+    }
+
+    #[test]
+    fn validates_flattened_refraction_params() {
+        let form = web::Query::<SatelliteDataForm>::from_query(
+            "satellite_name=ISS&lat=55.7&lon=37.6&alt=150&refraction=true&pressure_hpa=5000",
+        ).expect("Query should be parsed");
+
+        assert!(form.validate().is_err());
+    }
 }
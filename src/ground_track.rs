@@ -0,0 +1,194 @@
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone)]
+pub struct TrackPoint {
+    pub time: DateTime<Utc>,
+    pub lon: f64,
+    pub lat: f64,
+    /// Высота в километрах
+    pub alt: f64,
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Разбивает трассу на участки в местах пересечения меридиана ±180°, добавляя на концах
+/// участков точку пересечения с интерполированными широтой, высотой и временем
+pub fn split_at_antimeridian(points: &[TrackPoint]) -> Vec<Vec<TrackPoint>> {
+    let mut segments = vec![];
+    let mut current: Vec<TrackPoint> = vec![];
+
+    for point in points {
+        if let Some(previous) = current.last().cloned() {
+            let delta_lon = point.lon - previous.lon;
+
+            if delta_lon.abs() > 180.0 {
+                // Переходим к непрерывной долготе, чтобы найти долю пути до ±180°
+                let edge_lon = if delta_lon < 0.0 { 180.0 } else { -180.0 };
+                let unwrapped_lon = point.lon + 2.0 * edge_lon;
+                let fraction = (edge_lon - previous.lon) / (unwrapped_lon - previous.lon);
+
+                let step_milliseconds = (point.time - previous.time).num_milliseconds() as f64;
+                let crossing = TrackPoint {
+                    time: previous.time + Duration::milliseconds((step_milliseconds * fraction) as i64),
+                    lon: edge_lon,
+                    lat: previous.lat + (point.lat - previous.lat) * fraction,
+                    alt: previous.alt + (point.alt - previous.alt) * fraction,
+                };
+
+                current.push(crossing.clone());
+                segments.push(std::mem::take(&mut current));
+                current.push(TrackPoint { lon: -edge_lon, ..crossing });
+            }
+        }
+
+        current.push(point.clone());
+    }
+
+    if !current.is_empty() {
+        segments.push(current);
+    }
+
+    segments
+}
+
+/// GeoJSON с LineString или MultiLineString; время каждой вершины лежит в свойстве coordTimes,
+/// которое понимают QGIS, Leaflet и togeojson
+pub fn render_geojson(name: &str, norad_id: &str, points: &[TrackPoint]) -> Value {
+    let segments = split_at_antimeridian(points);
+
+    let coordinates: Vec<Vec<[f64; 3]>> = segments.iter()
+        .map(|segment| segment.iter().map(|point| [point.lon, point.lat, point.alt * 1000.0]).collect())
+        .collect();
+    let times: Vec<Vec<String>> = segments.iter()
+        .map(|segment| segment.iter().map(|point| format_time(point.time)).collect())
+        .collect();
+
+    let (geometry, coord_times) = if coordinates.len() == 1 {
+        (json!({"type": "LineString", "coordinates": coordinates[0]}), json!(times[0]))
+    } else {
+        (json!({"type": "MultiLineString", "coordinates": coordinates}), json!(times))
+    };
+
+    json!({
+        "type": "FeatureCollection",
+        "features": [{
+            "type": "Feature",
+            "geometry": geometry,
+            "properties": {
+                "name": name,
+                "norad_id": norad_id,
+                "coordTimes": coord_times,
+            },
+        }],
+    })
+}
+
+/// KML с gx:MultiTrack, где у каждой вершины есть свой элемент when
+pub fn render_kml(name: &str, norad_id: &str, points: &[TrackPoint]) -> String {
+    let mut tracks = String::new();
+
+    for segment in split_at_antimeridian(points) {
+        tracks.push_str("      <gx:Track>\n");
+
+        for point in &segment {
+            tracks.push_str(&format!("        <when>{}</when>\n", format_time(point.time)));
+        }
+
+        for point in &segment {
+            tracks.push_str(&format!(
+                "        <gx:coord>{} {} {}</gx:coord>\n",
+                point.lon, point.lat, point.alt * 1000.0,
+            ));
+        }
+
+        tracks.push_str("      </gx:Track>\n");
+    }
+
+    let name = escape_xml(name);
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<kml xmlns="http://www.opengis.net/kml/2.2" xmlns:gx="http://www.google.com/kml/ext/2.2">
+<Document>
+  <name>{name}</name>
+  <Placemark>
+    <name>{name}</name>
+    <description>NORAD {norad_id}</description>
+    <gx:MultiTrack>
+      <altitudeMode>absolute</altitudeMode>
+      <gx:interpolate>0</gx:interpolate>
+{tracks}    </gx:MultiTrack>
+  </Placemark>
+</Document>
+</kml>
+"#,
+        norad_id = escape_xml(norad_id),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn track(points: &[(i64, f64, f64)]) -> Vec<TrackPoint> {
+        let start_time = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+
+        points.iter()
+            .map(|&(seconds, lon, lat)| TrackPoint { time: start_time + Duration::seconds(seconds), lon, lat, alt: 420.0 })
+            .collect()
+    }
+
+    fn lon_lat(segment: &[TrackPoint]) -> Vec<(f64, f64)> {
+        segment.iter().map(|point| (point.lon, point.lat)).collect()
+    }
+
+    #[test]
+    fn splits_eastward_track_at_antimeridian() {
+        let points = track(&[(0, 170.0, 10.0), (60, 178.0, 12.0), (120, -176.0, 15.0), (180, -170.0, 17.0)]);
+        let segments = split_at_antimeridian(&points);
+
+        assert_eq!(segments.len(), 2);
+        assert_eq!(lon_lat(&segments[0]), [(170.0, 10.0), (178.0, 12.0), (180.0, 13.0)]);
+        assert_eq!(lon_lat(&segments[1]), [(-180.0, 13.0), (-176.0, 15.0), (-170.0, 17.0)]);
+
+        // До ±180° треть шага: 2° из 6°
+        let crossing_time = points[1].time + Duration::seconds(20);
+        assert_eq!(segments[0][2].time, crossing_time);
+        assert_eq!(segments[1][0].time, crossing_time);
+    }
+
+    #[test]
+    fn splits_westward_track_and_renders_multi_line_string() {
+        let points = track(&[(0, -175.0, -5.0), (60, 175.0, -5.0), (120, 165.0, -5.0)]);
+        let segments = split_at_antimeridian(&points);
+
+        assert_eq!(lon_lat(&segments[0]), [(-175.0, -5.0), (-180.0, -5.0)]);
+        assert_eq!(lon_lat(&segments[1]), [(180.0, -5.0), (175.0, -5.0), (165.0, -5.0)]);
+
+        let geojson = render_geojson("ISS", "25544", &points);
+        let geometry = &geojson["features"][0]["geometry"];
+        assert_eq!(geometry["type"], "MultiLineString");
+        assert_eq!(geometry["coordinates"].as_array().unwrap().len(), 2);
+        assert_eq!(geojson["features"][0]["properties"]["coordTimes"][1].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn keeps_track_without_crossing_in_one_segment() {
+        let points = track(&[(0, -10.0, 0.0), (60, 10.0, 5.0)]);
+
+        assert_eq!(split_at_antimeridian(&points).len(), 1);
+        assert_eq!(render_geojson("ISS", "25544", &points)["features"][0]["geometry"]["type"], "LineString");
+    }
+}
@@ -0,0 +1,109 @@
+use std::future::Future;
+use std::time::Duration as StdDuration;
+
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+const COMMAND_TIMEOUT: StdDuration = StdDuration::from_secs(3);
+
+#[derive(Debug, Error)]
+pub enum HamlibError {
+    #[error("Hamlib daemon input/output failed")]
+    IoError(#[from] std::io::Error),
+    #[error("Hamlib daemon did not answer in time")]
+    Timeout,
+    #[error("Hamlib daemon rejected the command with code {0}")]
+    Rejected(i32),
+    #[error("Failed to parse hamlib daemon answer {0:?}")]
+    InvalidAnswer(String),
+}
+
+/// Клиент текстового протокола rotctld/rigctld из Hamlib
+pub struct HamlibClient {
+    stream: BufReader<TcpStream>,
+}
+
+impl HamlibClient {
+    pub async fn connect(address: &str) -> Result<Self, HamlibError> {
+        let stream = tokio::time::timeout(COMMAND_TIMEOUT, TcpStream::connect(address)).await
+            .map_err(|_| HamlibError::Timeout)??;
+
+        Ok(HamlibClient { stream: BufReader::new(stream) })
+    }
+
+    pub async fn read_line(&mut self) -> Result<String, HamlibError> {
+        let mut line = String::new();
+
+        let read = tokio::time::timeout(COMMAND_TIMEOUT, self.stream.read_line(&mut line)).await
+            .map_err(|_| HamlibError::Timeout)??;
+
+        if read == 0 {
+            return Err(HamlibError::IoError(std::io::ErrorKind::UnexpectedEof.into()));
+        }
+
+        Ok(line.trim().to_string())
+    }
+
+    pub async fn send_command(&mut self, command: &str) -> Result<(), HamlibError> {
+        self.stream.get_mut().write_all(format!("{}\n", command).as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Читает ответ вида "RPRT <код>", который демоны присылают на команды установки
+    pub async fn read_report(&mut self) -> Result<(), HamlibError> {
+        let line = self.read_line().await?;
+
+        match line.strip_prefix("RPRT ").map(|code| code.trim().parse::<i32>()) {
+            Some(Ok(0)) => Ok(()),
+            Some(Ok(code)) => Err(HamlibError::Rejected(code)),
+            _ => Err(HamlibError::InvalidAnswer(line)),
+        }
+    }
+
+    /// Отправляет команду установки и ждет RPRT 0
+    pub async fn execute(&mut self, command: &str) -> Result<(), HamlibError> {
+        self.send_command(command).await?;
+        self.read_report().await
+    }
+
+    /// Читает числовое значение из ответа на команду чтения
+    pub async fn read_value(&mut self) -> Result<f64, HamlibError> {
+        let line = self.read_line().await?;
+
+        if let Some(code) = line.strip_prefix("RPRT ") {
+            return Err(HamlibError::Rejected(code.trim().parse().unwrap_or(-1)));
+        }
+
+        line.parse().map_err(|_| HamlibError::InvalidAnswer(line))
+    }
+}
+
+/// Фоновая задача, которую можно остановить и дождаться ее завершения
+pub struct StoppableTask {
+    stop_sender: oneshot::Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl StoppableTask {
+    pub fn spawn<F, Fut>(task: F) -> Self
+        where
+            F: FnOnce(oneshot::Receiver<()>) -> Fut,
+            Fut: Future<Output = ()> + 'static,
+    {
+        let (stop_sender, stop_receiver) = oneshot::channel();
+        let handle = actix_rt::spawn(task(stop_receiver));
+
+        StoppableTask { stop_sender, handle }
+    }
+
+    pub async fn stop(self) {
+        let _ = self.stop_sender.send(());
+
+        if let Err(error) = self.handle.await {
+            log::warn!("Background task failed: {}", error);
+        }
+    }
+}
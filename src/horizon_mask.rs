@@ -0,0 +1,431 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::calculations::EARTH_RADIUS_KM;
+
+/// Шаг по азимуту маски, построенной по карте высот
+const DEM_AZIMUTH_STEP: f64 = 1.0;
+/// Дальше этого расстояния препятствия на карте высот не учитываются
+const DEM_MAX_DISTANCE_KM: f64 = 50.0;
+const KM_PER_DEGREE: f64 = 111.32;
+
+#[derive(Debug, Error)]
+pub enum HorizonMaskError {
+    #[error("Failed to read or write horizon mask file")]
+    IoError(#[from] std::io::Error),
+    #[error("Horizon mask name should contain only latin letters, digits, '-' and '_'")]
+    InvalidName,
+    #[error("Horizon mask {0:?} doesn't exist")]
+    MaskNotFound(String),
+    #[error("Invalid horizon mask profile: {0}")]
+    InvalidProfile(String),
+    #[error("DEM file {0:?} doesn't exist")]
+    DemNotFound(String),
+    #[error("Invalid DEM file: {0}")]
+    InvalidDem(String),
+    #[error("Observer is outside of the DEM file")]
+    ObserverOutsideDem,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MaskPoint {
+    pub azimuth: f64,
+    pub elevation: f64,
+}
+
+/// Минимальный угол места (градусы) в зависимости от азимута, между точками профиля линейная интерполяция
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
+pub struct HorizonMask {
+    points: Vec<MaskPoint>,
+}
+
+impl HorizonMask {
+    pub fn new(mut points: Vec<MaskPoint>) -> Result<Self, HorizonMaskError> {
+        if points.is_empty() {
+            return Err(HorizonMaskError::InvalidProfile("profile is empty".to_string()));
+        }
+
+        for point in &mut points {
+            if !point.azimuth.is_finite() || !(-90.0..=90.0).contains(&point.elevation) {
+                return Err(HorizonMaskError::InvalidProfile(format!(
+                    "point {} {} is out of range", point.azimuth, point.elevation,
+                )));
+            }
+
+            point.azimuth = point.azimuth.rem_euclid(360.0);
+        }
+
+        points.sort_by(|a, b| a.azimuth.total_cmp(&b.azimuth));
+
+        Ok(HorizonMask { points })
+    }
+
+    /// JSON-массив точек вида {"azimuth": 0, "elevation": 5}
+    pub fn parse_json(profile: &str) -> Result<Self, HorizonMaskError> {
+        let points = serde_json::from_str(profile)
+            .map_err(|error| HorizonMaskError::InvalidProfile(error.to_string()))?;
+
+        HorizonMask::new(points)
+    }
+
+    /// Строки "азимут,угол места"; пустые строки, комментарии после # и заголовок пропускаются
+    pub fn parse_csv(profile: &str) -> Result<Self, HorizonMaskError> {
+        let mut points = vec![];
+
+        for (index, line) in profile.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let values: Vec<_> = line.split([',', ';', '\t']).map(str::trim).collect();
+
+            match values.as_slice() {
+                [azimuth, elevation] => match (azimuth.parse(), elevation.parse()) {
+                    (Ok(azimuth), Ok(elevation)) => points.push(MaskPoint { azimuth, elevation }),
+                    _ if points.is_empty() => continue,
+                    _ => return Err(HorizonMaskError::InvalidProfile(format!("line {} is not numeric", index + 1))),
+                },
+                _ => return Err(HorizonMaskError::InvalidProfile(format!("line {} should have two columns", index + 1))),
+            }
+        }
+
+        HorizonMask::new(points)
+    }
+
+    /// Маска по карте высот в формате ESRI ASCII Grid с координатами в градусах.
+    /// Для каждого азимута берется наибольший угол места рельефа с учетом кривизны Земли
+    pub fn from_dem(dem: &str, lat: f64, lon: f64, alt: f64) -> Result<Self, HorizonMaskError> {
+        let grid = DemGrid::parse(dem)?;
+
+        let ground_height = grid.height_at(lat, lon).ok_or(HorizonMaskError::ObserverOutsideDem)?;
+        let observer_height = alt.max(ground_height);
+
+        let distance_step = grid.cellsize * KM_PER_DEGREE / 2.0;
+        let steps = (DEM_MAX_DISTANCE_KM / distance_step).ceil() as usize;
+
+        let mut points = vec![];
+        let mut azimuth: f64 = 0.0;
+
+        while azimuth < 360.0 {
+            let mut max_elevation = f64::NEG_INFINITY;
+
+            for step in 1..=steps {
+                let distance = step as f64 * distance_step;
+                let point_lat = lat + distance * azimuth.to_radians().cos() / KM_PER_DEGREE;
+                let point_lon = lon + distance * azimuth.to_radians().sin() / (KM_PER_DEGREE * lat.to_radians().cos());
+
+                let Some(height) = grid.height_at(point_lat, point_lon) else {
+                    if grid.contains(point_lat, point_lon) { continue; } else { break; }
+                };
+
+                let curvature_drop = (distance * 1000.0).powi(2) / (2.0 * EARTH_RADIUS_KM * 1000.0);
+                let elevation = (height - observer_height - curvature_drop).atan2(distance * 1000.0).to_degrees();
+
+                max_elevation = max_elevation.max(elevation);
+            }
+
+            if max_elevation.is_finite() {
+                points.push(MaskPoint { azimuth, elevation: max_elevation.clamp(-90.0, 90.0) });
+            }
+
+            azimuth += DEM_AZIMUTH_STEP;
+        }
+
+        HorizonMask::new(points)
+    }
+
+    /// Минимальный угол места для азимута, оба в градусах
+    pub fn min_elevation(&self, azimuth: f64) -> f64 {
+        let azimuth = azimuth.rem_euclid(360.0);
+        let index = self.points.partition_point(|point| point.azimuth <= azimuth);
+
+        let (previous, next) = match index {
+            0 => (self.points[self.points.len() - 1], self.points[0]),
+            index if index == self.points.len() => (self.points[index - 1], self.points[0]),
+            index => (self.points[index - 1], self.points[index]),
+        };
+
+        let span = (next.azimuth - previous.azimuth).rem_euclid(360.0);
+
+        if span == 0.0 {
+            return previous.elevation;
+        }
+
+        let fraction = (azimuth - previous.azimuth).rem_euclid(360.0) / span;
+        previous.elevation + (next.elevation - previous.elevation) * fraction
+    }
+}
+
+/// Карта высот в формате ESRI ASCII Grid
+struct DemGrid {
+    ncols: usize,
+    nrows: usize,
+    xllcorner: f64,
+    yllcorner: f64,
+    cellsize: f64,
+    nodata_value: Option<f64>,
+    heights: Vec<f64>,
+}
+
+impl DemGrid {
+    fn parse(dem: &str) -> Result<Self, HorizonMaskError> {
+        let invalid = |message: &str| HorizonMaskError::InvalidDem(message.to_string());
+
+        let mut ncols = None;
+        let mut nrows = None;
+        let mut xllcorner = None;
+        let mut yllcorner = None;
+        let mut is_center_registered = false;
+        let mut cellsize = None;
+        let mut nodata_value = None;
+        let mut heights = vec![];
+
+        for line in dem.lines() {
+            let mut tokens = line.split_whitespace();
+
+            let Some(first) = tokens.next() else { continue };
+
+            if first.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                let value: f64 = tokens.next()
+                    .and_then(|value| value.parse().ok())
+                    .ok_or_else(|| invalid("header value is not numeric"))?;
+
+                match first.to_lowercase().as_str() {
+                    "ncols" => ncols = Some(value as usize),
+                    "nrows" => nrows = Some(value as usize),
+                    "xllcorner" => xllcorner = Some(value),
+                    "yllcorner" => yllcorner = Some(value),
+                    "xllcenter" => (xllcorner, is_center_registered) = (Some(value), true),
+                    "yllcenter" => (yllcorner, is_center_registered) = (Some(value), true),
+                    "cellsize" => cellsize = Some(value),
+                    "nodata_value" => nodata_value = Some(value),
+                    _ => return Err(invalid("unknown header key")),
+                }
+
+                continue;
+            }
+
+            for token in std::iter::once(first).chain(tokens) {
+                heights.push(token.parse().map_err(|_| invalid("height is not numeric"))?);
+            }
+        }
+
+        let (Some(ncols), Some(nrows), Some(xllcorner), Some(yllcorner), Some(cellsize)) =
+            (ncols, nrows, xllcorner, yllcorner, cellsize) else {
+            return Err(invalid("header is incomplete"));
+        };
+
+        if cellsize <= 0.0 || heights.len() != ncols * nrows {
+            return Err(invalid("grid size doesn't match the header"));
+        }
+
+        // В заголовке с xllcenter/yllcenter указан центр угловой ячейки, а не ее угол
+        let (xllcorner, yllcorner) = if is_center_registered {
+            (xllcorner - cellsize / 2.0, yllcorner - cellsize / 2.0)
+        } else {
+            (xllcorner, yllcorner)
+        };
+
+        Ok(DemGrid { ncols, nrows, xllcorner, yllcorner, cellsize, nodata_value, heights })
+    }
+
+    fn cell(&self, lat: f64, lon: f64) -> Option<(usize, usize)> {
+        let col = ((lon - self.xllcorner) / self.cellsize).floor();
+        let row_from_bottom = ((lat - self.yllcorner) / self.cellsize).floor();
+
+        if col < 0.0 || row_from_bottom < 0.0 || col >= self.ncols as f64 || row_from_bottom >= self.nrows as f64 {
+            return None;
+        }
+
+        Some((self.nrows - 1 - row_from_bottom as usize, col as usize))
+    }
+
+    fn contains(&self, lat: f64, lon: f64) -> bool {
+        self.cell(lat, lon).is_some()
+    }
+
+    /// Высота ближайшей ячейки в метрах, None вне карты и в ячейках без данных
+    fn height_at(&self, lat: f64, lon: f64) -> Option<f64> {
+        let (row, col) = self.cell(lat, lon)?;
+        let height = self.heights[row * self.ncols + col];
+
+        (Some(height) != self.nodata_value).then_some(height)
+    }
+}
+
+/// Маски горизонта, сохраненные на диске под именами, и каталог с картами высот для их построения
+pub struct HorizonMaskStore {
+    masks_directory: PathBuf,
+    dem_directory: PathBuf,
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= 64 &&
+        name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+impl HorizonMaskStore {
+    pub fn new(masks_directory: &str, dem_directory: &str) -> Self {
+        HorizonMaskStore {
+            masks_directory: PathBuf::from(masks_directory),
+            dem_directory: PathBuf::from(dem_directory),
+        }
+    }
+
+    fn mask_path(&self, name: &str) -> Result<PathBuf, HorizonMaskError> {
+        if !is_valid_name(name) {
+            return Err(HorizonMaskError::InvalidName);
+        }
+
+        Ok(self.masks_directory.join(format!("{}.json", name)))
+    }
+
+    pub async fn load(&self, name: &str) -> Result<HorizonMask, HorizonMaskError> {
+        let profile = match tokio::fs::read_to_string(self.mask_path(name)?).await {
+            Ok(profile) => profile,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Err(HorizonMaskError::MaskNotFound(name.to_string()));
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        HorizonMask::parse_json(&profile)
+    }
+
+    /// Маска по имени, если оно указано у запроса или станции
+    pub async fn load_optional(&self, name: Option<&str>) -> Result<Option<HorizonMask>, HorizonMaskError> {
+        match name {
+            Some(name) => self.load(name).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn save(&self, name: &str, mask: &HorizonMask) -> Result<(), HorizonMaskError> {
+        let path = self.mask_path(name)?;
+        let tmp_path = path.with_extension("json.tmp");
+
+        tokio::fs::create_dir_all(&self.masks_directory).await?;
+        tokio::fs::write(&tmp_path, serde_json::to_string_pretty(mask).expect("Mask should be serializable")).await?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+
+        Ok(())
+    }
+
+    /// Карта высот из каталога DEM; имя файла без расширения, сам файл с расширением .asc
+    pub async fn read_dem(&self, name: &str) -> Result<String, HorizonMaskError> {
+        if !is_valid_name(name) {
+            return Err(HorizonMaskError::InvalidName);
+        }
+
+        match tokio::fs::read_to_string(self.dem_directory.join(format!("{}.asc", name))).await {
+            Ok(dem) => Ok(dem),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                Err(HorizonMaskError::DemNotFound(name.to_string()))
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mask(points: &[(f64, f64)]) -> HorizonMask {
+        HorizonMask::new(points.iter().map(|&(azimuth, elevation)| MaskPoint { azimuth, elevation }).collect())
+            .expect("Test mask should be valid")
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    /// Ровная карта 21x21 ячейка по 0.01° с хребтом высотой 1000 м в крайнем восточном столбце
+    /// registration - "corner" или "center", origin - координаты угла или центра юго-западной ячейки
+    fn ridge_dem(registration: &str, origin: f64) -> String {
+        let row = format!("{} 1000", vec!["0"; 20].join(" "));
+        let rows = vec![row; 21].join("\n");
+
+        format!(
+            "ncols 21\nnrows 21\nxll{0} {1}\nyll{0} {1}\ncellsize 0.01\nNODATA_value -9999\n{2}\n",
+            registration, origin, rows,
+        )
+    }
+
+    #[test]
+    fn interpolates_across_north() {
+        let mask = mask(&[(350.0, 10.0), (10.0, 2.0)]);
+
+        assert_close(mask.min_elevation(0.0), 6.0);
+        assert_close(mask.min_elevation(355.0), 8.0);
+        assert_close(mask.min_elevation(-5.0), 8.0);
+        assert_close(mask.min_elevation(5.0), 4.0);
+        assert_close(mask.min_elevation(180.0), 6.0);
+    }
+
+    #[test]
+    fn single_point_mask_is_flat() {
+        let mask = mask(&[(45.0, 7.0)]);
+
+        for azimuth in [0.0, 45.0, 90.0, 359.9] {
+            assert_close(mask.min_elevation(azimuth), 7.0);
+        }
+    }
+
+    #[test]
+    fn csv_skips_header_and_comments() {
+        let mask = HorizonMask::parse_csv("azimuth,elevation\n# north is blocked\n0, 10 # mast\n\n180;0\n")
+            .expect("Profile should parse");
+
+        assert_close(mask.min_elevation(0.0), 10.0);
+        assert_close(mask.min_elevation(90.0), 5.0);
+        assert_close(mask.min_elevation(180.0), 0.0);
+    }
+
+    #[test]
+    fn csv_rejects_non_numeric_row_after_data() {
+        let error = HorizonMask::parse_csv("azimuth,elevation\n0,10\nnorth,5\n").unwrap_err();
+
+        assert_eq!(error.to_string(), "Invalid horizon mask profile: line 3 is not numeric");
+    }
+
+    #[test]
+    fn dem_ridge_raises_mask_at_its_azimuth() {
+        let (lat, lon) = (0.105, 0.105);
+        let mask = HorizonMask::from_dem(&ridge_dem("corner", 0.0), lat, lon, 0.0).expect("Mask should be built");
+
+        // До хребта около 10.6 км, поэтому он поднимает горизонт примерно на 5°
+        let east = mask.min_elevation(90.0);
+        assert!((4.5..6.0).contains(&east), "Ridge elevation is {}", east);
+        assert!(mask.min_elevation(270.0) < 0.0);
+
+        let highest = (0..360).map(f64::from)
+            .max_by(|a, b| mask.min_elevation(*a).total_cmp(&mask.min_elevation(*b)))
+            .unwrap();
+        assert!((80.0..=100.0).contains(&highest), "Highest point is at azimuth {}", highest);
+    }
+
+    #[test]
+    fn dem_center_registered_header_matches_corner_one() {
+        let (lat, lon) = (0.105, 0.105);
+        let corner = ridge_dem("corner", 0.0);
+        let center = ridge_dem("center", 0.005);
+
+        let corner_mask = HorizonMask::from_dem(&corner, lat, lon, 0.0).expect("Mask should be built");
+        let center_mask = HorizonMask::from_dem(&center, lat, lon, 0.0).expect("Mask should be built");
+
+        for azimuth in (0..360).map(f64::from) {
+            assert_close(center_mask.min_elevation(azimuth), corner_mask.min_elevation(azimuth));
+        }
+
+        assert!(matches!(
+            HorizonMask::from_dem(&center, 1.0, 1.0, 0.0),
+            Err(HorizonMaskError::ObserverOutsideDem),
+        ));
+    }
+}
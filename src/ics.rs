@@ -0,0 +1,170 @@
+use chrono::{DateTime, Utc};
+
+use super::calculations::PassData;
+
+/// Сетка округления времени кульминации в UID, если номер витка неизвестен
+const UID_ROUNDING_MINUTES: i64 = 10;
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Переносит строки длиннее 75 октетов, как требует RFC 5545
+fn fold_line(line: &str) -> String {
+    let mut result = String::new();
+    let mut line_length = 0;
+
+    for character in line.chars() {
+        if line_length + character.len_utf8() > 75 {
+            result.push_str("\r\n ");
+            line_length = 1;
+        }
+
+        result.push(character);
+        line_length += character.len_utf8();
+    }
+
+    result.push_str("\r\n");
+    result
+}
+
+/// Обновление tle сдвигает пролет на секунды, а номер витка в кульминации остается прежним
+/// (см. calculations::count_pass_revolutions), поэтому календарь обновляет событие, а не дублирует его
+fn get_uid(pass_data: &PassData, revolution_number: Option<i64>, observer_lat: f64, observer_lon: f64) -> String {
+    let pass_key = match revolution_number {
+        Some(revolution_number) => format!("rev{}", revolution_number),
+        None => {
+            let rounding_seconds = UID_ROUNDING_MINUTES * 60;
+            let apogee_seconds = pass_data.apogee_time.timestamp();
+
+            ((apogee_seconds + rounding_seconds / 2).div_euclid(rounding_seconds) * rounding_seconds).to_string()
+        }
+    };
+
+    format!("{}-{}-{:.2}_{:.2}@orbitalik", pass_data.norad_id, pass_key, observer_lat, observer_lon)
+}
+
+/// get_revolution_number дает номер витка спутника в кульминации пролета, если он известен
+pub fn render_passes(
+    passes: &[PassData],
+    observer_lat: f64, observer_lon: f64,
+    get_revolution_number: impl Fn(&PassData) -> Option<i64>,
+) -> String {
+    let now = format_time(Utc::now());
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//Orbitalik//Satellite passes//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+    ];
+
+    for pass_data in passes {
+        let description = [
+            format!(
+                "Rise: {} UTC, azimuth {:.1}°",
+                pass_data.rise_time.format("%H:%M:%S"), pass_data.rise_azimuth,
+            ),
+            format!(
+                "Max elevation: {:.1}° at {} UTC, azimuth {:.1}°",
+                pass_data.apogee_elevation, pass_data.apogee_time.format("%H:%M:%S"), pass_data.apogee_azimuth,
+            ),
+            format!(
+                "Set: {} UTC, azimuth {:.1}°",
+                pass_data.fall_time.format("%H:%M:%S"), pass_data.fall_azimuth,
+            ),
+        ].join("\n");
+        let summary = format!("{} pass, max {:.0}°", pass_data.satellite_name, pass_data.apogee_elevation);
+
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}", get_uid(pass_data, get_revolution_number(pass_data), observer_lat, observer_lon)),
+            format!("DTSTAMP:{}", now),
+            format!("DTSTART:{}", format_time(pass_data.rise_time)),
+            format!("DTEND:{}", format_time(pass_data.fall_time)),
+            format!("SUMMARY:{}", escape_text(&summary)),
+            format!("DESCRIPTION:{}", escape_text(&description)),
+            "TRANSP:TRANSPARENT".to_string(),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| fold_line(line)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+    use crate::calculations::{self, Illumination, ObserverLighting};
+
+    fn pass_at(apogee_time: DateTime<Utc>) -> PassData {
+        PassData {
+            satellite_name: "ISS (ZARYA)".to_string(),
+            norad_id: "25544".to_string(),
+            rise_time: apogee_time - Duration::minutes(5),
+            rise_azimuth: 200.0,
+            apogee_time,
+            apogee_elevation: 80.0,
+            apogee_azimuth: 110.0,
+            fall_time: apogee_time + Duration::minutes(5),
+            fall_azimuth: 20.0,
+            illumination: Illumination::Sunlit,
+            observer_lighting: ObserverLighting::Darkness,
+            visible_intervals: vec![],
+            apogee_magnitude: None,
+        }
+    }
+
+    #[test]
+    fn uid_survives_small_shifts_of_a_pass_culminating_at_the_ascending_node() {
+        // Около 15.5 витков в сутки, наблюдатель на экваторе видит кульминацию ровно в узле
+        let mean_motion = 15.5 * satellite::constants::TWO_PI / 1440.0;
+        let apogee_time = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        let node_argument_of_latitude = 10.0 * satellite::constants::TWO_PI;
+
+        let uid = |shift_seconds: f64| {
+            let shifted = apogee_time + Duration::milliseconds((shift_seconds * 1000.0) as i64);
+            let argument_of_latitude = node_argument_of_latitude + mean_motion * shift_seconds / 60.0;
+            let revolution_number = calculations::count_pass_revolutions(42_000, argument_of_latitude, 0.0);
+
+            get_uid(&pass_at(shifted), Some(revolution_number), 0.0, 30.0)
+        };
+
+        for shift_seconds in [-5.0, -1.0, -0.1, 0.1, 1.0, 5.0] {
+            assert_eq!(uid(shift_seconds), uid(0.0), "Shift by {} s changed the uid", shift_seconds);
+        }
+
+        let orbital_period_seconds = satellite::constants::TWO_PI / mean_motion * 60.0;
+        assert_ne!(uid(orbital_period_seconds), uid(0.0), "The next pass should get its own uid");
+    }
+
+    #[test]
+    fn revolutions_start_away_from_culminations_in_both_hemispheres() {
+        let node = 4.0 * satellite::constants::TWO_PI;
+        let quarter = satellite::constants::TWO_PI / 4.0;
+
+        // Северный наблюдатель: граница витка в самой южной точке орбиты
+        assert_eq!(calculations::count_pass_revolutions(100, node - 0.01, 50.0), 103);
+        assert_eq!(calculations::count_pass_revolutions(100, node + 0.01, 50.0), 103);
+        assert_eq!(calculations::count_pass_revolutions(100, node + quarter, 50.0), 103);
+        assert_eq!(calculations::count_pass_revolutions(100, node - quarter + 0.01, 50.0), 103);
+
+        // Южный наблюдатель: граница в самой северной точке
+        assert_eq!(calculations::count_pass_revolutions(100, node - 0.01, -50.0), 103);
+        assert_eq!(calculations::count_pass_revolutions(100, node + 0.01, -50.0), 103);
+        assert_eq!(calculations::count_pass_revolutions(100, node - quarter, -50.0), 103);
+        assert_eq!(calculations::count_pass_revolutions(100, node + quarter + 0.01, -50.0), 104);
+    }
+}
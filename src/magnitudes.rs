@@ -0,0 +1,29 @@
+use std::collections::HashMap;
+
+use super::catalog::normalize_norad_id;
+
+/// Стандартные звездные величины спутников (на расстоянии 1000 км при фазовом угле 90°)
+pub struct StandardMagnitudes {
+    by_norad_id: HashMap<String, f64>,
+}
+
+impl StandardMagnitudes {
+    pub fn empty() -> Self {
+        StandardMagnitudes { by_norad_id: HashMap::new() }
+    }
+
+    pub fn load(path: &str) -> std::io::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let table: HashMap<String, f64> = serde_json::from_str(&content)?;
+
+        let by_norad_id = table.into_iter()
+            .map(|(norad_id, magnitude)| (normalize_norad_id(&norad_id), magnitude))
+            .collect();
+
+        Ok(StandardMagnitudes { by_norad_id })
+    }
+
+    pub fn get(&self, satrec: &satellite::io::Satrec) -> Option<f64> {
+        self.by_norad_id.get(&normalize_norad_id(&satrec.satnum)).copied()
+    }
+}
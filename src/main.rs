@@ -1,4 +1,3 @@
-use std::time::Duration;
 use actix_web::{web, App, HttpServer, middleware::Logger};
 use actix_cors::Cors;
 
@@ -7,6 +6,23 @@ mod forms;
 mod calculations;
 mod fetch_tle;
 mod serializers;
+mod catalog;
+mod magnitudes;
+mod rotator;
+mod rig;
+mod hamlib;
+mod ics;
+mod delimited;
+mod czml;
+mod ground_track;
+mod footprint;
+mod horizon_mask;
+mod stations;
+mod coverage;
+mod scheduler;
+mod tle_history;
+mod omm;
+mod tle_parser;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -15,28 +31,117 @@ async fn main() -> std::io::Result<()> {
 
     std::env::set_var("TLE_FETCHING_SETTINGS_PATH", "data/tle_fetching_settings.json");
     std::env::set_var("TLE_FILE_PATH", "data/tle.txt");
+    std::env::set_var("STANDARD_MAGNITUDES_PATH", "data/standard_magnitudes.json");
+    std::env::set_var("ROTATOR_SETTINGS_PATH", "data/rotator_settings.json");
+    std::env::set_var("RIG_SETTINGS_PATH", "data/rig_settings.json");
+    std::env::set_var("HORIZON_MASKS_PATH", "data/horizon_masks");
+    std::env::set_var("DEM_PATH", "data/dem");
+    std::env::set_var("STATIONS_PATH", "data/stations.json");
+    std::env::set_var("TLE_HISTORY_PATH", "data/tle_history");
+    std::env::set_var("TLE_SOURCE_CACHES_PATH", "data/tle_source_caches.json");
 
-    actix_rt::spawn(async {
+    let initial_catalog = match catalog::Catalog::load(&std::env::var("TLE_FILE_PATH").unwrap()) {
+        Ok(initial_catalog) => initial_catalog,
+        Err(error) => {
+            log::warn!("Failed to load tle file, starting with empty catalog: {:?}", error);
+            catalog::Catalog::parse("")
+        }
+    };
+    let catalog = web::Data::new(catalog::CatalogStore::new(initial_catalog));
+
+    let standard_magnitudes = match magnitudes::StandardMagnitudes::load(
+        &std::env::var("STANDARD_MAGNITUDES_PATH").unwrap(),
+    ) {
+        Ok(standard_magnitudes) => standard_magnitudes,
+        Err(error) => {
+            log::warn!("Failed to load standard magnitudes, brightness will be unknown: {:?}", error);
+            magnitudes::StandardMagnitudes::empty()
+        }
+    };
+    let standard_magnitudes = web::Data::new(standard_magnitudes);
+
+    let stations_path = std::env::var("STATIONS_PATH").unwrap();
+    let stations = match stations::StationRegistry::load(&stations_path) {
+        Ok(stations) => stations,
+        Err(error) => {
+            log::error!("Failed to load stations, the registry is read-only until the file is fixed: {:?}", error);
+            stations::StationRegistry::read_only(&stations_path)
+        }
+    };
+    let stations = web::Data::new(stations);
+
+    let rotator_controller = web::Data::new(rotator::RotatorController::default());
+    let rig_controller = web::Data::new(rig::RigController::default());
+    let horizon_masks = web::Data::new(horizon_mask::HorizonMaskStore::new(
+        &std::env::var("HORIZON_MASKS_PATH").unwrap(),
+        &std::env::var("DEM_PATH").unwrap(),
+    ));
+
+    let tle_history = web::Data::new(tle_history::TleHistory::load(&std::env::var("TLE_HISTORY_PATH").unwrap()).await);
+
+    let fetch_reports = web::Data::new(fetch_tle::FetchReportStore::default());
+
+    let fetching_catalog = catalog.clone();
+    let fetching_tle_history = tle_history.clone();
+    let fetching_reports = fetch_reports.clone();
+    actix_rt::spawn(async move {
         let mut fetching_settings = fetch_tle::read_settings().await;
-        let mut interval = actix_rt::time::interval(Duration::from_secs(fetching_settings.delay_seconds));
+        let mut source_caches = fetch_tle::read_source_caches().await;
+        let mut consecutive_failures = 0;
         loop {
-            interval.tick().await;
-            match fetch_tle::fetch_tle(&mut fetching_settings).await {
-                Ok(_) => log::info!("Tle fethcing success"),
-                Err(error) => log::warn!("Failed to fetch tle with this error: {:?}", error),
+            match fetch_tle::fetch_tle(
+                &mut fetching_settings, &mut source_caches, &fetching_catalog, &fetching_tle_history,
+                &fetching_reports,
+            ).await {
+                Ok(_) => {
+                    log::info!("Tle fethcing success");
+                    consecutive_failures = 0;
+                }
+                Err(error) => {
+                    log::warn!("Failed to fetch tle, keeping the last good catalog: {:?}", error);
+                    consecutive_failures += 1;
+                }
             };
+            actix_rt::time::sleep(fetch_tle::get_next_fetch_delay(&fetching_settings, consecutive_failures)).await;
         }
     });
 
-    HttpServer::new(|| {
+    HttpServer::new(move || {
         App::new()
             .wrap(Logger::default())
+            .app_data(catalog.clone())
+            .app_data(standard_magnitudes.clone())
+            .app_data(fetch_reports.clone())
+            .app_data(rotator_controller.clone())
+            .app_data(rig_controller.clone())
+            .app_data(horizon_masks.clone())
+            .app_data(stations.clone())
+            .app_data(tle_history.clone())
             .wrap(Cors::default().allow_any_origin().allow_any_method().allow_any_header())  // TODO Удалить
             .app_data(web::FormConfig::default().error_handler(forms::parse_error_handler))
             .route("api/get-satellites-list", web::get().to(views::get_satellites_list))
+            .route("api/get-fetch-report", web::get().to(views::get_fetch_report))
             .route("api/get-satellite-data", web::get().to(views::get_satellite_data))
             .route("api/get-passes-list", web::get().to(views::get_passes_list))
+            .route("api/get-network-passes", web::get().to(views::get_network_passes))
+            .route("api/get-contact-schedule", web::get().to(views::get_contact_schedule))
             .route("api/get-trajectory", web::get().to(views::get_trajectory))
+            .route("api/get-czml", web::get().to(views::get_czml))
+            .route("api/get-footprint", web::get().to(views::get_footprint))
+            .route("api/stations", web::get().to(views::get_stations))
+            .route("api/stations/{id}", web::get().to(views::get_station))
+            .route("api/stations/{id}", web::post().to(views::create_station))
+            .route("api/stations/{id}", web::put().to(views::update_station))
+            .route("api/stations/{id}", web::delete().to(views::delete_station))
+            .route("api/horizon-masks/{name}", web::get().to(views::get_horizon_mask))
+            .route("api/horizon-masks/{name}", web::post().to(views::upload_horizon_mask))
+            .route("api/horizon-masks/{name}/dem", web::post().to(views::create_horizon_mask_from_dem))
+            .route("api/rotator/start", web::post().to(views::start_rotator))
+            .route("api/rotator/stop", web::post().to(views::stop_rotator))
+            .route("api/rotator/status", web::get().to(views::get_rotator_status))
+            .route("api/rig/start", web::post().to(views::start_rig))
+            .route("api/rig/stop", web::post().to(views::stop_rig))
+            .route("api/rig/status", web::get().to(views::get_rig_status))
     }).bind(("127.0.0.1", 8080))?
         .run()
         .await
@@ -0,0 +1,394 @@
+use std::collections::HashMap;
+
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::catalog::ALPHA5_LETTERS;
+use super::tle_parser::get_checksum;
+
+#[derive(Debug, Error)]
+pub enum OmmError {
+    #[error("OMM JSON is malformed")]
+    InvalidJson(#[from] serde_json::Error),
+    #[error("OMM record has no {0} field")]
+    MissingField(&'static str),
+    #[error("OMM field {field} has invalid value {value:?}")]
+    InvalidField { field: &'static str, value: String },
+    #[error("NORAD id {0} doesn't fit into the Alpha-5 tle format")]
+    UnsupportedNoradId(u32),
+}
+
+/// Формат, в котором источник отдает элементы орбит
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceFormat {
+    #[default]
+    Tle,
+    OmmJson,
+    OmmXml,
+    OmmKvn,
+}
+
+impl SourceFormat {
+    /// Отсекает страницы с ошибками, которые сервер отдает вместо данных
+    pub fn accepts_content_type(&self, content_type: &str) -> bool {
+        if content_type.contains("html") {
+            return false;
+        }
+
+        match self {
+            SourceFormat::Tle | SourceFormat::OmmKvn => content_type.starts_with("text/plain")
+                || content_type.starts_with("application/octet-stream"),
+            SourceFormat::OmmJson => content_type.contains("json"),
+            SourceFormat::OmmXml => content_type.contains("xml"),
+        }
+    }
+}
+
+/// Поля одного сообщения OMM, ключи как в стандарте CCSDS (OBJECT_NAME, MEAN_MOTION и т. д.)
+type OmmFields = HashMap<String, String>;
+
+/// CelesTrak и Space-Track отдают массив объектов, где числа бывают и числами, и строками
+fn parse_json(omm: &str) -> Result<Vec<OmmFields>, OmmError> {
+    let records: Vec<HashMap<String, serde_json::Value>> = serde_json::from_str(omm)?;
+
+    Ok(records.into_iter()
+        .map(|record| {
+            record.into_iter()
+                .filter_map(|(key, value)| match value {
+                    serde_json::Value::String(value) => Some((key.to_uppercase(), value)),
+                    serde_json::Value::Number(value) => Some((key.to_uppercase(), value.to_string())),
+                    _ => None,
+                })
+                .collect()
+        })
+        .collect())
+}
+
+/// Стандартные и числовые ссылки на символы XML, например "&amp;" и "&#38;"
+fn decode_xml_entities(value: &str) -> String {
+    let mut result = String::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let decoded = rest.find(';').and_then(|end| {
+            let character = match &rest[1..end] {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                entity => entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X"))
+                    .map(|hex| u32::from_str_radix(hex, 16).ok())
+                    .unwrap_or_else(|| entity.strip_prefix('#').and_then(|decimal| decimal.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+
+            character.map(|character| (character, end))
+        });
+
+        match decoded {
+            Some((character, end)) => {
+                result.push(character);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Простые элементы вида <KEY>value</KEY>, каждое сообщение начинается с тега <omm
+fn parse_xml(omm: &str) -> Vec<OmmFields> {
+    omm.split("<omm").skip(1)
+        .map(|message| {
+            let mut fields = OmmFields::new();
+            let mut rest = message;
+
+            while let Some(start) = rest.find('<') {
+                rest = &rest[start + 1..];
+
+                let Some(tag_end) = rest.find('>') else { break };
+                let tag = rest[..tag_end].split_whitespace().next().unwrap_or_default().to_string();
+                rest = &rest[tag_end + 1..];
+
+                if tag.starts_with('/') || tag.ends_with('/') {
+                    continue;
+                }
+
+                if let Some(value_end) = rest.find(&format!("</{}>", tag)) {
+                    let value = &rest[..value_end];
+
+                    if !value.contains('<') {
+                        fields.insert(tag.to_uppercase(), decode_xml_entities(value.trim()));
+                    }
+                }
+            }
+
+            fields
+        })
+        .collect()
+}
+
+/// Строки KEY = VALUE [единицы], каждое сообщение начинается с CCSDS_OMM_VERS
+fn parse_kvn(omm: &str) -> Vec<OmmFields> {
+    let mut records: Vec<OmmFields> = vec![];
+
+    for line in omm.lines() {
+        let Some((key, value)) = line.split_once('=') else { continue };
+        let key = key.trim().to_uppercase();
+
+        if key == "CCSDS_OMM_VERS" {
+            records.push(OmmFields::new());
+        }
+
+        let value = value.split('[').next().unwrap_or_default().trim().to_string();
+
+        if let Some(record) = records.last_mut() {
+            record.insert(key, value);
+        }
+    }
+
+    records
+}
+
+fn get_field<'a>(fields: &'a OmmFields, field: &'static str) -> Result<&'a str, OmmError> {
+    fields.get(field).map(String::as_str).ok_or(OmmError::MissingField(field))
+}
+
+fn get_number<T: std::str::FromStr>(fields: &OmmFields, field: &'static str) -> Result<T, OmmError> {
+    let value = get_field(fields, field)?;
+
+    value.trim().parse().map_err(|_| OmmError::InvalidField { field, value: value.to_string() })
+}
+
+fn get_optional_number(fields: &OmmFields, field: &'static str) -> Result<f64, OmmError> {
+    match fields.get(field) {
+        Some(_) => get_number(fields, field),
+        None => Ok(0.0),
+    }
+}
+
+/// Номер NORAD в формате Alpha-5: номера от 100000 записываются буквой вместо двух старших цифр.
+/// Номера от 340000 в tle не помещаются, такие сообщения OMM пропускаются
+fn format_norad_id(norad_id: u32) -> Result<String, OmmError> {
+    match norad_id {
+        0..=99_999 => Ok(format!("{:05}", norad_id)),
+        100_000..=339_999 => {
+            let letter = ALPHA5_LETTERS[(norad_id / 10_000 - 10) as usize] as char;
+            Ok(format!("{}{:04}", letter, norad_id % 10_000))
+        }
+        _ => Err(OmmError::UnsupportedNoradId(norad_id)),
+    }
+}
+
+/// "1998-067A" в вид из tle "98067A  "
+fn format_international_designator(object_id: &str) -> String {
+    let designator = match object_id.split_once('-') {
+        Some((year, rest)) if year.len() == 4 => format!("{}{}", &year[2..], rest),
+        _ => object_id.to_string(),
+    };
+
+    format!("{:<8.8}", designator)
+}
+
+/// Эпоха в виде YYDDD.DDDDDDDD
+fn format_epoch(epoch: &str) -> Result<String, OmmError> {
+    let epoch_time = NaiveDateTime::parse_from_str(epoch.trim_end_matches('Z'), "%Y-%m-%dT%H:%M:%S%.f")
+        .map_err(|_| OmmError::InvalidField { field: "EPOCH", value: epoch.to_string() })?;
+
+    let seconds_of_day = epoch_time.num_seconds_from_midnight() as f64
+        + epoch_time.nanosecond() as f64 / 1e9;
+    let day_of_year = epoch_time.ordinal() as f64 + seconds_of_day / 86_400.0;
+
+    Ok(format!("{:02}{:012.8}", epoch_time.year() % 100, day_of_year))
+}
+
+/// Производная среднего движения в виде " .00001234"
+fn format_decimal_fraction(value: f64) -> String {
+    let sign = if value < 0.0 { '-' } else { ' ' };
+    let digits = format!("{:.8}", value.abs().min(0.999_999_99));
+
+    format!("{}{}", sign, &digits[1..])
+}
+
+/// Число в виде " 12345-4" с подразумеваемой десятичной точкой перед мантиссой
+fn format_exponential(value: f64) -> String {
+    if value == 0.0 {
+        return " 00000+0".to_string();
+    }
+
+    let sign = if value < 0.0 { '-' } else { ' ' };
+    let mut exponent = value.abs().log10().floor() as i32 + 1;
+    let mut mantissa = (value.abs() / 10f64.powi(exponent) * 1e5).round() as i64;
+
+    if mantissa >= 100_000 {
+        mantissa /= 10;
+        exponent += 1;
+    }
+
+    if exponent < -9 {
+        return " 00000+0".to_string();
+    }
+
+    let exponent_sign = if exponent < 0 { '-' } else { '+' };
+
+    format!("{}{:05}{}{}", sign, mantissa, exponent_sign, exponent.abs().min(9))
+}
+
+/// Переводит сообщение OMM в три строки tle, чтобы дальше с ним работал тот же каталог
+fn to_tle(fields: &OmmFields) -> Result<String, OmmError> {
+    let name = fields.get("OBJECT_NAME").map(String::as_str).unwrap_or("UNKNOWN");
+    let norad_id = format_norad_id(get_number(fields, "NORAD_CAT_ID")?)?;
+    let classification = fields.get("CLASSIFICATION_TYPE").and_then(|value| value.chars().next()).unwrap_or('U');
+    let international_designator = format_international_designator(
+        fields.get("OBJECT_ID").map(String::as_str).unwrap_or_default(),
+    );
+    let epoch = format_epoch(get_field(fields, "EPOCH")?)?;
+
+    let eccentricity: f64 = get_number(fields, "ECCENTRICITY")?;
+    let element_set_number = get_optional_number(fields, "ELEMENT_SET_NO")? as u32 % 10_000;
+    let revolution_number = get_optional_number(fields, "REV_AT_EPOCH")? as u32 % 100_000;
+
+    let line1 = format!(
+        "1 {}{} {} {} {} {} {} 0 {:>4}",
+        norad_id, classification, international_designator, epoch,
+        format_decimal_fraction(get_optional_number(fields, "MEAN_MOTION_DOT")?),
+        format_exponential(get_optional_number(fields, "MEAN_MOTION_DDOT")?),
+        format_exponential(get_optional_number(fields, "BSTAR")?),
+        element_set_number,
+    );
+
+    let line2 = format!(
+        "2 {} {:8.4} {:8.4} {:07.0} {:8.4} {:8.4} {:11.8}{:5}",
+        norad_id,
+        get_number::<f64>(fields, "INCLINATION")?,
+        get_number::<f64>(fields, "RA_OF_ASC_NODE")?,
+        (eccentricity * 1e7).round(),
+        get_number::<f64>(fields, "ARG_OF_PERICENTER")?,
+        get_number::<f64>(fields, "MEAN_ANOMALY")?,
+        get_number::<f64>(fields, "MEAN_MOTION")?,
+        revolution_number,
+    );
+
+    Ok(format!(
+        "{}\n{}{}\n{}{}\n",
+        name.trim(), line1, get_checksum(&line1), line2, get_checksum(&line2),
+    ))
+}
+
+/// Приводит ответ источника к тексту tle; сообщения OMM, которые не удалось перевести, пропускаются с предупреждением
+pub fn normalize_to_tle(content: &str, format: SourceFormat) -> Result<String, OmmError> {
+    let records = match format {
+        SourceFormat::Tle => return Ok(content.to_string()),
+        SourceFormat::OmmJson => parse_json(content)?,
+        SourceFormat::OmmXml => parse_xml(content),
+        SourceFormat::OmmKvn => parse_kvn(content),
+    };
+
+    let mut tle = String::new();
+
+    for fields in &records {
+        match to_tle(fields) {
+            Ok(record) => tle.push_str(&record),
+            Err(error) => log::warn!(
+                "Skipping OMM record {:?}: {}",
+                fields.get("OBJECT_NAME").map(String::as_str).unwrap_or_default(), error,
+            ),
+        }
+    }
+
+    Ok(tle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::normalize_norad_id;
+    use crate::tle_parser;
+
+    /// Элементы ISS из примера формата tle, переписанные в поля OMM
+    const ISS_OMM_JSON: &str = r#"[{
+        "OBJECT_NAME": "ISS (ZARYA)",
+        "OBJECT_ID": "1998-067A",
+        "EPOCH": "2008-09-20T12:25:40.104192",
+        "MEAN_MOTION": 15.72125391,
+        "ECCENTRICITY": 0.0006703,
+        "INCLINATION": 51.6416,
+        "RA_OF_ASC_NODE": 247.4627,
+        "ARG_OF_PERICENTER": 130.536,
+        "MEAN_ANOMALY": 325.0288,
+        "CLASSIFICATION_TYPE": "U",
+        "NORAD_CAT_ID": 25544,
+        "ELEMENT_SET_NO": 292,
+        "REV_AT_EPOCH": 56353,
+        "BSTAR": -1.1606e-5,
+        "MEAN_MOTION_DOT": -2.182e-5,
+        "MEAN_MOTION_DDOT": 0
+    }]"#;
+
+    const ISS_TLE: &str = "\
+ISS (ZARYA)
+1 25544U 98067A   08264.51782528 -.00002182  00000+0 -11606-4 0  2926
+2 25544  51.6416 247.4627 0006703 130.5360 325.0288 15.72125391563537
+";
+
+    fn iss_fields() -> OmmFields {
+        parse_json(ISS_OMM_JSON).expect("Fixture should be valid JSON").remove(0)
+    }
+
+    #[test]
+    fn converts_omm_to_tle_columns_and_checksums() {
+        let tle = to_tle(&iss_fields()).expect("Fixture should convert");
+
+        assert_eq!(tle, ISS_TLE);
+
+        let parsed = tle_parser::parse(&tle);
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        assert_eq!(parsed.records.len(), 1);
+    }
+
+    #[test]
+    fn writes_large_norad_ids_in_alpha5() {
+        let mut fields = iss_fields();
+        fields.insert("NORAD_CAT_ID".to_string(), "100001".to_string());
+
+        let tle = to_tle(&fields).expect("Alpha-5 id should convert");
+        let record = tle_parser::parse(&tle).records.remove(0);
+
+        assert_eq!(&record.line1[2..7], "A0001");
+        assert_eq!(&record.line2[2..7], "A0001");
+        assert_eq!(record.norad_id(), "100001");
+        assert_eq!(normalize_norad_id("A0001"), normalize_norad_id("100001"));
+        assert_eq!(normalize_norad_id("Z9999"), "339999");
+    }
+
+    #[test]
+    fn rejects_norad_ids_that_dont_fit_into_tle() {
+        let mut fields = iss_fields();
+        fields.insert("NORAD_CAT_ID".to_string(), "340000".to_string());
+
+        assert!(matches!(to_tle(&fields), Err(OmmError::UnsupportedNoradId(340_000))));
+    }
+
+    #[test]
+    fn decodes_xml_entities() {
+        let xml = "<ndm><omm id=\"1\"><body><OBJECT_NAME>CUBE &amp; SAT &#8470;1</OBJECT_NAME>\
+            <NORAD_CAT_ID>25544</NORAD_CAT_ID></body></omm></ndm>";
+
+        let records = parse_xml(xml);
+
+        assert_eq!(records[0]["OBJECT_NAME"], "CUBE & SAT №1");
+        assert_eq!(records[0]["NORAD_CAT_ID"], "25544");
+        assert_eq!(decode_xml_entities("a &lt; b &unknown; &"), "a < b &unknown; &");
+    }
+}
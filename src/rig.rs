@@ -0,0 +1,281 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration as StdDuration;
+
+use actix_web::web;
+use chrono::Utc;
+use serde::{de, Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::oneshot;
+
+use super::calculations;
+use super::catalog::{normalize_norad_id, CatalogStore};
+use super::hamlib::{HamlibClient, HamlibError, StoppableTask};
+
+#[derive(Debug, Error)]
+pub enum RigError {
+    #[error("Failed to read rig settings file")]
+    SettingsLoadingFailed(#[from] std::io::Error),
+    #[error("Rig settings file is malformed")]
+    InvalidSettings(#[from] serde_json::Error),
+    #[error("Transponder {0:?} is not configured for this satellite")]
+    TransponderNotFound(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Transponder {
+    pub norad_id: String,
+    pub name: String,
+    pub downlink_hz: f64,
+    /// Частота приема спутника; None для маяков и спутников без ретранслятора
+    pub uplink_hz: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RigSettings {
+    /// Адрес rigctld в виде host:port
+    pub address: String,
+    pub update_interval_ms: u64,
+    /// Новая частота отправляется, только если поправка изменилась больше чем на столько герц
+    pub tolerance_hz: f64,
+    /// VFO передачи в режиме split, например VFOB или Sub
+    #[serde(default = "default_split_tx_vfo")]
+    pub split_tx_vfo: String,
+    pub transponders: Vec<Transponder>,
+}
+
+fn default_split_tx_vfo() -> String {
+    "VFOB".to_string()
+}
+
+impl RigSettings {
+    pub fn find_transponder(&self, norad_id: &str, name: &str) -> Result<Transponder, RigError> {
+        let norad_id = normalize_norad_id(norad_id);
+
+        self.transponders.iter()
+            .find(|transponder| {
+                normalize_norad_id(&transponder.norad_id) == norad_id && transponder.name == name
+            })
+            .cloned()
+            .ok_or(RigError::TransponderNotFound(name.to_string()))
+    }
+}
+
+pub async fn read_settings() -> Result<RigSettings, RigError> {
+    let path = std::env::var("RIG_SETTINGS_PATH")
+        .expect("RIG_SETTINGS_PATH env variable should be set");
+
+    let content = tokio::fs::read_to_string(path).await?;
+    let settings: RigSettings = serde_json::from_str(&content)?;
+
+    // Нулевой интервал уронил бы задачу в tokio::time::interval уже после запуска
+    if settings.update_interval_ms == 0 {
+        return Err(RigError::InvalidSettings(de::Error::custom("update_interval_ms should be positive")));
+    }
+
+    Ok(settings)
+}
+
+/// Команды rigctld поверх общего клиента Hamlib
+pub struct RigctldClient {
+    client: HamlibClient,
+}
+
+impl RigctldClient {
+    pub async fn connect(address: &str) -> Result<Self, HamlibError> {
+        Ok(RigctldClient { client: HamlibClient::connect(address).await? })
+    }
+
+    /// Частота приема (текущий VFO)
+    pub async fn set_frequency(&mut self, frequency_hz: f64) -> Result<(), HamlibError> {
+        self.client.execute(&format!("F {:.0}", frequency_hz)).await
+    }
+
+    /// Включает split: передача идет на tx_vfo, прием остается на текущем VFO
+    pub async fn enable_split(&mut self, tx_vfo: &str) -> Result<(), HamlibError> {
+        self.client.execute(&format!("S 1 {}", tx_vfo)).await
+    }
+
+    /// Частота передачи в режиме split
+    pub async fn set_split_frequency(&mut self, frequency_hz: f64) -> Result<(), HamlibError> {
+        self.client.execute(&format!("I {:.0}", frequency_hz)).await
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct RigStatus {
+    pub is_tuning: bool,
+    pub satellite_name: Option<String>,
+    pub transponder: Option<Transponder>,
+    pub range_rate: Option<f64>,
+    pub corrected_downlink_hz: Option<f64>,
+    pub corrected_uplink_hz: Option<f64>,
+    pub last_error: Option<String>,
+}
+
+/// Раз в update_interval_ms пересчитывает доплеровский сдвиг и перестраивает радиостанцию
+struct TuningTask {
+    catalog: web::Data<CatalogStore>,
+    norad_id: String,
+    observer: satellite::Geodedic,
+    settings: RigSettings,
+    transponder: Transponder,
+    status: Arc<Mutex<RigStatus>>,
+    client: Option<RigctldClient>,
+    last_downlink_hz: Option<f64>,
+    last_uplink_hz: Option<f64>,
+}
+
+fn needs_update(last_frequency: Option<f64>, frequency: f64, tolerance_hz: f64) -> bool {
+    match last_frequency {
+        Some(last_frequency) => (frequency - last_frequency).abs() > tolerance_hz,
+        None => true,
+    }
+}
+
+impl TuningTask {
+    fn update_status<F: FnOnce(&mut RigStatus)>(&self, update: F) {
+        update(&mut self.status.lock().expect("Rig status lock shouldn't be poisoned"));
+    }
+
+    fn compute_range_rate(&self) -> Result<f64, String> {
+        let catalog = self.catalog.snapshot();
+        let satrec = catalog.find_satrec(&self.norad_id).map_err(|error| error.to_string())?;
+
+        let (_, range_rate) = calculations::get_range_and_range_rate(
+            satrec, &self.observer, Utc::now(),
+        ).map_err(|error| error.to_string())?;
+
+        Ok(range_rate)
+    }
+
+    async fn tune(&mut self, downlink_hz: f64, uplink_hz: Option<f64>) -> Result<(), HamlibError> {
+        if self.client.is_none() {
+            let mut client = RigctldClient::connect(&self.settings.address).await?;
+
+            // Без split поправка частоты передачи ушла бы не на тот VFO
+            if self.transponder.uplink_hz.is_some() {
+                client.enable_split(&self.settings.split_tx_vfo).await?;
+            }
+
+            self.client = Some(client);
+        }
+
+        let client = self.client.as_mut().expect("Client was connected above");
+
+        if needs_update(self.last_downlink_hz, downlink_hz, self.settings.tolerance_hz) {
+            client.set_frequency(downlink_hz).await?;
+            self.last_downlink_hz = Some(downlink_hz);
+        }
+
+        if let Some(uplink_hz) = uplink_hz {
+            if needs_update(self.last_uplink_hz, uplink_hz, self.settings.tolerance_hz) {
+                client.set_split_frequency(uplink_hz).await?;
+                self.last_uplink_hz = Some(uplink_hz);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn tick(&mut self) {
+        let range_rate = match self.compute_range_rate() {
+            Ok(range_rate) => range_rate,
+            Err(error) => {
+                log::warn!("Failed to compute doppler shift: {}", error);
+                self.update_status(|status| status.last_error = Some(error));
+                return;
+            }
+        };
+
+        let downlink_hz = calculations::get_downlink_frequency(self.transponder.downlink_hz, range_rate);
+        let uplink_hz = self.transponder.uplink_hz
+            .map(|uplink_hz| calculations::get_uplink_frequency(uplink_hz, range_rate));
+
+        self.update_status(|status| {
+            status.range_rate = Some(range_rate);
+            status.corrected_downlink_hz = Some(downlink_hz);
+            status.corrected_uplink_hz = uplink_hz;
+        });
+
+        match self.tune(downlink_hz, uplink_hz).await {
+            Ok(()) => self.update_status(|status| status.last_error = None),
+            Err(error) => {
+                log::warn!("Rig command failed, reconnecting on next update: {}", error);
+                self.client = None;
+                self.last_downlink_hz = None;
+                self.last_uplink_hz = None;
+                self.update_status(|status| status.last_error = Some(error.to_string()));
+            }
+        }
+    }
+
+    async fn run(mut self, mut stop_receiver: oneshot::Receiver<()>) {
+        let update_interval = StdDuration::from_millis(self.settings.update_interval_ms);
+        let mut interval = tokio::time::interval(update_interval);
+
+        loop {
+            tokio::select! {
+                _ = &mut stop_receiver => break,
+                _ = interval.tick() => self.tick().await,
+            }
+        }
+
+        self.update_status(|status| status.is_tuning = false);
+    }
+}
+
+#[derive(Default)]
+pub struct RigController {
+    /// Держится от остановки старой задачи до запуска новой, как у ротатора
+    tuning: tokio::sync::Mutex<Option<StoppableTask>>,
+    status: Arc<Mutex<RigStatus>>,
+}
+
+impl RigController {
+    pub async fn start(
+        &self,
+        catalog: web::Data<CatalogStore>,
+        norad_id: String, satellite_name: String,
+        observer: satellite::Geodedic,
+        settings: RigSettings, transponder: Transponder,
+    ) {
+        let mut tuning = self.tuning.lock().await;
+
+        if let Some(previous) = tuning.take() {
+            previous.stop().await;
+        }
+
+        *self.status.lock().expect("Rig status lock shouldn't be poisoned") = RigStatus {
+            is_tuning: true,
+            satellite_name: Some(satellite_name),
+            transponder: Some(transponder.clone()),
+            ..Default::default()
+        };
+
+        let task = TuningTask {
+            catalog,
+            norad_id,
+            observer,
+            settings,
+            transponder,
+            status: self.status.clone(),
+            client: None,
+            last_downlink_hz: None,
+            last_uplink_hz: None,
+        };
+
+        *tuning = Some(StoppableTask::spawn(|stop_receiver| task.run(stop_receiver)));
+    }
+
+    pub async fn stop(&self) {
+        let mut tuning = self.tuning.lock().await;
+
+        if let Some(tuning) = tuning.take() {
+            tuning.stop().await;
+        }
+    }
+
+    pub fn status(&self) -> RigStatus {
+        self.status.lock().expect("Rig status lock shouldn't be poisoned").clone()
+    }
+}
@@ -0,0 +1,411 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration as StdDuration;
+
+use actix_web::web;
+use chrono::{DateTime, Duration, Utc};
+use serde::{de, Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::oneshot;
+
+use super::calculations;
+use super::catalog::CatalogStore;
+use super::hamlib::{HamlibClient, HamlibError, StoppableTask};
+
+/// Шаг, с которым просматривается предстоящий пролет при выборе стороны обхода азимута
+const PLANNING_STEP_SECONDS: i64 = 10;
+const PLANNING_HORIZON_HOURS: i64 = 24;
+/// Если пролета в горизонте планирования нет, следующий поиск не раньше чем через столько минут
+const PLANNING_RETRY_MINUTES: i64 = 60;
+
+#[derive(Debug, Error)]
+pub enum RotatorError {
+    #[error("Failed to read rotator settings file")]
+    SettingsLoadingFailed(#[from] std::io::Error),
+    #[error("Rotator settings file is malformed")]
+    InvalidSettings(#[from] serde_json::Error),
+}
+
+/// Сектор, в котором антенну нельзя опускать ниже min_elevation (мачты, крыши и т.п.)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KeepOutZone {
+    pub from_azimuth: f64,
+    pub to_azimuth: f64,
+    pub min_elevation: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RotatorSettings {
+    /// Адрес rotctld в виде host:port
+    pub address: String,
+    pub update_interval_ms: u64,
+    /// Новая команда отправляется, только если цель сместилась больше чем на столько градусов
+    pub tolerance_degrees: f64,
+    /// Пределы ротатора; max_azimuth больше 360 означает, что ротатор умеет заходить за север
+    pub min_azimuth: f64,
+    pub max_azimuth: f64,
+    pub min_elevation: f64,
+    pub max_elevation: f64,
+    pub keep_out_zones: Vec<KeepOutZone>,
+}
+
+pub async fn read_settings() -> Result<RotatorSettings, RotatorError> {
+    let path = std::env::var("ROTATOR_SETTINGS_PATH")
+        .expect("ROTATOR_SETTINGS_PATH env variable should be set");
+
+    let content = tokio::fs::read_to_string(path).await?;
+    let settings: RotatorSettings = serde_json::from_str(&content)?;
+
+    // Нулевой интервал уронил бы задачу в tokio::time::interval уже после запуска
+    if settings.update_interval_ms == 0 {
+        return Err(RotatorError::InvalidSettings(de::Error::custom("update_interval_ms should be positive")));
+    }
+
+    Ok(settings)
+}
+
+/// Команды rotctld поверх общего клиента Hamlib
+pub struct RotctldClient {
+    client: HamlibClient,
+}
+
+impl RotctldClient {
+    pub async fn connect(address: &str) -> Result<Self, HamlibError> {
+        Ok(RotctldClient { client: HamlibClient::connect(address).await? })
+    }
+
+    pub async fn set_position(&mut self, azimuth: f64, elevation: f64) -> Result<(), HamlibError> {
+        self.client.execute(&format!("P {:.2} {:.2}", azimuth, elevation)).await
+    }
+
+    pub async fn get_position(&mut self) -> Result<(f64, f64), HamlibError> {
+        self.client.send_command("p").await?;
+
+        let azimuth = self.client.read_value().await?;
+        let elevation = self.client.read_value().await?;
+
+        Ok((azimuth, elevation))
+    }
+
+    pub async fn stop(&mut self) -> Result<(), HamlibError> {
+        self.client.execute("S").await
+    }
+}
+
+/// Разворачивает азимуты пролета в непрерывную кривую и сдвигает ее на целое число оборотов
+/// так, чтобы она целиком поместилась в пределы ротатора. Возвращает начальный азимут пути
+fn fit_azimuth_path(azimuths: &[f64], min_azimuth: f64, max_azimuth: f64) -> Option<f64> {
+    let first = *azimuths.first()?;
+
+    let mut unwrapped = vec![first];
+
+    for &azimuth in &azimuths[1..] {
+        let previous = *unwrapped.last().expect("Path always contains the first azimuth");
+        let mut delta = (azimuth - previous).rem_euclid(360.0);
+
+        if delta > 180.0 {
+            delta -= 360.0;
+        }
+
+        unwrapped.push(previous + delta);
+    }
+
+    let lowest = unwrapped.iter().copied().fold(f64::INFINITY, f64::min);
+    let highest = unwrapped.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+    let turns = ((min_azimuth - lowest) / 360.0).ceil();
+
+    if highest + turns * 360.0 <= max_azimuth {
+        Some(first + turns * 360.0)
+    } else {
+        None
+    }
+}
+
+/// Выбирает представление азимута (az + 360k) в пределах ротатора, ближайшее к предыдущей команде
+fn resolve_azimuth(azimuth: f64, previous: Option<f64>, min_azimuth: f64, max_azimuth: f64) -> f64 {
+    let base = azimuth.rem_euclid(360.0);
+
+    let candidates = (-2..=2)
+        .map(|turns| base + turns as f64 * 360.0)
+        .filter(|candidate| (min_azimuth..=max_azimuth).contains(candidate));
+
+    let best = match previous {
+        Some(previous) => candidates
+            .min_by(|a, b| (a - previous).abs().total_cmp(&(b - previous).abs())),
+        None => candidates.min_by(|a, b| a.total_cmp(b)),
+    };
+
+    // Если ротатор не перекрывает полный круг, а цель вне его пределов, упираемся в ближайший предел
+    best.unwrap_or_else(|| base.clamp(min_azimuth, max_azimuth))
+}
+
+fn in_keep_out_zone(zone: &KeepOutZone, azimuth: f64) -> bool {
+    let azimuth = azimuth.rem_euclid(360.0);
+    let from = zone.from_azimuth.rem_euclid(360.0);
+    let to = zone.to_azimuth.rem_euclid(360.0);
+
+    if from <= to {
+        (from..=to).contains(&azimuth)
+    } else {
+        azimuth >= from || azimuth <= to
+    }
+}
+
+fn apply_limits(settings: &RotatorSettings, azimuth: f64, elevation: f64) -> f64 {
+    let mut elevation = elevation;
+
+    for zone in &settings.keep_out_zones {
+        if in_keep_out_zone(zone, azimuth) && elevation < zone.min_elevation {
+            elevation = zone.min_elevation;
+        }
+    }
+
+    elevation.clamp(settings.min_elevation, settings.max_elevation)
+}
+
+/// Начальный азимут ближайшего (или текущего) пролета с учетом обхода через север
+fn plan_pass_azimuth(
+    satrec: &satellite::io::Satrec,
+    observer: &satellite::Geodedic,
+    from_time: DateTime<Utc>,
+    settings: &RotatorSettings,
+) -> Result<Option<f64>, calculations::PassesCalculationError> {
+    let until_time = from_time + Duration::hours(PLANNING_HORIZON_HOURS);
+    let step = Duration::seconds(PLANNING_STEP_SECONDS);
+
+    let mut current_time = from_time;
+    let mut look = calculations::get_observer_look(satrec, current_time, observer)?;
+
+    while look.elevation < 0.0 && current_time < until_time {
+        current_time += step;
+        look = calculations::get_observer_look(satrec, current_time, observer)?;
+    }
+
+    let mut azimuths = vec![];
+
+    while look.elevation >= 0.0 && current_time < until_time {
+        azimuths.push(look.azimuth);
+        current_time += step;
+        look = calculations::get_observer_look(satrec, current_time, observer)?;
+    }
+
+    Ok(fit_azimuth_path(&azimuths, settings.min_azimuth, settings.max_azimuth)
+        .or_else(|| azimuths.first().map(|&azimuth| {
+            resolve_azimuth(azimuth, None, settings.min_azimuth, settings.max_azimuth)
+        })))
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct RotatorStatus {
+    pub is_tracking: bool,
+    pub satellite_name: Option<String>,
+    pub target_azimuth: Option<f64>,
+    pub target_elevation: Option<f64>,
+    pub rotator_azimuth: Option<f64>,
+    pub rotator_elevation: Option<f64>,
+    pub last_error: Option<String>,
+}
+
+/// Следит за одним спутником и раз в update_interval_ms отправляет rotctld новое положение
+struct TrackingTask {
+    catalog: web::Data<CatalogStore>,
+    norad_id: String,
+    observer: satellite::Geodedic,
+    settings: RotatorSettings,
+    status: Arc<Mutex<RotatorStatus>>,
+    client: Option<RotctldClient>,
+    previous_azimuth: Option<f64>,
+    /// Начальный азимут следующего пролета; Some(None) - пролета в горизонте планирования не нашлось
+    parked_azimuth: Option<Option<f64>>,
+    /// Когда можно снова искать пролет, которого не нашлось
+    next_planning_time: DateTime<Utc>,
+    last_command: Option<(f64, f64)>,
+}
+
+impl TrackingTask {
+    fn update_status<F: FnOnce(&mut RotatorStatus)>(&self, update: F) {
+        update(&mut self.status.lock().expect("Rotator status lock shouldn't be poisoned"));
+    }
+
+    fn compute_target(&mut self, time: DateTime<Utc>) -> Result<(f64, f64), String> {
+        let catalog = self.catalog.snapshot();
+        let satrec = catalog.find_satrec(&self.norad_id).map_err(|error| error.to_string())?;
+
+        let look = calculations::get_observer_look(satrec, time, &self.observer)
+            .map_err(|error| error.to_string())?;
+
+        if look.elevation < 0.0 {
+            // Под горизонтом заранее разворачиваемся к точке восхода следующего пролета
+            let needs_planning = match self.parked_azimuth {
+                None => true,
+                Some(None) => time >= self.next_planning_time,
+                Some(Some(_)) => false,
+            };
+
+            if needs_planning {
+                self.parked_azimuth = Some(plan_pass_azimuth(satrec, &self.observer, time, &self.settings)
+                    .map_err(|error| error.to_string())?);
+                self.next_planning_time = time + Duration::minutes(PLANNING_RETRY_MINUTES);
+            }
+
+            let azimuth = self.parked_azimuth.flatten().unwrap_or_else(|| resolve_azimuth(
+                look.azimuth, self.previous_azimuth,
+                self.settings.min_azimuth, self.settings.max_azimuth,
+            ));
+            self.previous_azimuth = Some(azimuth);
+
+            return Ok((azimuth, apply_limits(&self.settings, azimuth, self.settings.min_elevation)));
+        }
+
+        self.parked_azimuth = None;
+
+        if self.previous_azimuth.is_none() {
+            self.previous_azimuth = plan_pass_azimuth(satrec, &self.observer, time, &self.settings)
+                .map_err(|error| error.to_string())?;
+        }
+
+        let azimuth = resolve_azimuth(
+            look.azimuth, self.previous_azimuth,
+            self.settings.min_azimuth, self.settings.max_azimuth,
+        );
+        self.previous_azimuth = Some(azimuth);
+
+        Ok((azimuth, apply_limits(&self.settings, azimuth, look.elevation)))
+    }
+
+    async fn point(&mut self, azimuth: f64, elevation: f64) -> Result<(f64, f64), HamlibError> {
+        if self.client.is_none() {
+            self.client = Some(RotctldClient::connect(&self.settings.address).await?);
+        }
+
+        let client = self.client.as_mut().expect("Client was connected above");
+
+        let needs_command = match self.last_command {
+            Some((last_azimuth, last_elevation)) => {
+                (azimuth - last_azimuth).abs() > self.settings.tolerance_degrees ||
+                    (elevation - last_elevation).abs() > self.settings.tolerance_degrees
+            }
+            None => true,
+        };
+
+        if needs_command {
+            client.set_position(azimuth, elevation).await?;
+            self.last_command = Some((azimuth, elevation));
+        }
+
+        client.get_position().await
+    }
+
+    async fn tick(&mut self) {
+        let (azimuth, elevation) = match self.compute_target(Utc::now()) {
+            Ok(target) => target,
+            Err(error) => {
+                log::warn!("Failed to compute rotator target: {}", error);
+                self.update_status(|status| status.last_error = Some(error));
+                return;
+            }
+        };
+
+        self.update_status(|status| {
+            status.target_azimuth = Some(azimuth);
+            status.target_elevation = Some(elevation);
+        });
+
+        match self.point(azimuth, elevation).await {
+            Ok((rotator_azimuth, rotator_elevation)) => self.update_status(|status| {
+                status.rotator_azimuth = Some(rotator_azimuth);
+                status.rotator_elevation = Some(rotator_elevation);
+                status.last_error = None;
+            }),
+            Err(error) => {
+                log::warn!("Rotator command failed, reconnecting on next update: {}", error);
+                self.client = None;
+                self.last_command = None;
+                self.update_status(|status| status.last_error = Some(error.to_string()));
+            }
+        }
+    }
+
+    async fn run(mut self, mut stop_receiver: oneshot::Receiver<()>) {
+        let update_interval = StdDuration::from_millis(self.settings.update_interval_ms);
+        let mut interval = tokio::time::interval(update_interval);
+
+        loop {
+            tokio::select! {
+                _ = &mut stop_receiver => break,
+                _ = interval.tick() => self.tick().await,
+            }
+        }
+
+        if let Some(client) = &mut self.client {
+            if let Err(error) = client.stop().await {
+                log::warn!("Failed to stop rotator: {}", error);
+            }
+        }
+
+        self.update_status(|status| status.is_tracking = false);
+    }
+}
+
+#[derive(Default)]
+pub struct RotatorController {
+    /// Асинхронная блокировка держится от остановки старой задачи до запуска новой,
+    /// иначе одновременные запуски оставили бы лишнюю задачу слежения
+    tracking: tokio::sync::Mutex<Option<StoppableTask>>,
+    status: Arc<Mutex<RotatorStatus>>,
+}
+
+impl RotatorController {
+    pub async fn start(
+        &self,
+        catalog: web::Data<CatalogStore>,
+        norad_id: String, satellite_name: String,
+        observer: satellite::Geodedic,
+        settings: RotatorSettings,
+    ) {
+        let mut tracking = self.tracking.lock().await;
+
+        if let Some(previous) = tracking.take() {
+            previous.stop().await;
+        }
+
+        *self.status.lock().expect("Rotator status lock shouldn't be poisoned") = RotatorStatus {
+            is_tracking: true,
+            satellite_name: Some(satellite_name),
+            ..Default::default()
+        };
+
+        let task = TrackingTask {
+            catalog,
+            norad_id,
+            observer,
+            settings,
+            status: self.status.clone(),
+            client: None,
+            previous_azimuth: None,
+            parked_azimuth: None,
+            next_planning_time: Utc::now(),
+            last_command: None,
+        };
+
+        *tracking = Some(StoppableTask::spawn(|stop_receiver| task.run(stop_receiver)));
+    }
+
+    /// Останавливает слежение и ждет, пока ротатор получит команду S.
+    /// Возвращает false, если слежение не было запущено
+    pub async fn stop(&self) -> bool {
+        let mut tracking = self.tracking.lock().await;
+
+        match tracking.take() {
+            Some(tracking) => {
+                tracking.stop().await;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn status(&self) -> RotatorStatus {
+        self.status.lock().expect("Rotator status lock shouldn't be poisoned").clone()
+    }
+}
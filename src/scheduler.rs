@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use super::calculations::PassData;
+use super::catalog::normalize_norad_id;
+use super::serializers::SerializablePassData;
+
+/// Приоритет спутников, для которых он не указан
+const DEFAULT_PRIORITY: u32 = 1;
+
+/// Ограничения одной антенны
+pub struct ScheduleConstraints {
+    /// Приоритеты по номеру NORAD без ведущих нулей
+    pub priorities: HashMap<String, u32>,
+    /// Более короткие сеансы связи бесполезны
+    pub min_contact: Duration,
+    /// Время на разворот антенны между сеансами
+    pub slew: Duration,
+}
+
+impl ScheduleConstraints {
+    fn priority(&self, pass_data: &PassData) -> u32 {
+        self.priorities.get(&normalize_norad_id(&pass_data.norad_id)).copied().unwrap_or(DEFAULT_PRIORITY)
+    }
+
+    /// Вес сеанса: приоритет, умноженный на длительность в минутах
+    fn weight(&self, pass_data: &PassData) -> f64 {
+        self.priority(pass_data) as f64 * get_duration(pass_data).num_milliseconds() as f64 / 60_000.0
+    }
+
+    /// Между сеансами должно оставаться время на разворот антенны
+    fn conflicts(&self, a: &PassData, b: &PassData) -> bool {
+        a.rise_time < b.fall_time + self.slew && b.rise_time < a.fall_time + self.slew
+    }
+}
+
+fn get_duration(pass_data: &PassData) -> Duration {
+    pass_data.fall_time - pass_data.rise_time
+}
+
+#[derive(Serialize)]
+pub struct ScheduledContact {
+    #[serde(flatten)]
+    pub pass_data: SerializablePassData,
+    pub priority: u32,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum DropReason {
+    TooShort { duration_seconds: f64, min_contact_seconds: f64 },
+    Conflict { conflicting_norad_id: String, conflicting_rise_time: DateTime<Utc> },
+    /// Пролет ни с чем не конфликтует, но и не увеличивает суммарный вес плана
+    NoGain,
+}
+
+#[derive(Serialize)]
+pub struct DroppedPass {
+    #[serde(flatten)]
+    pub pass_data: SerializablePassData,
+    pub priority: u32,
+    #[serde(flatten)]
+    pub reason: DropReason,
+    pub explanation: String,
+}
+
+#[derive(Serialize)]
+pub struct Schedule {
+    pub contacts: Vec<ScheduledContact>,
+    pub dropped: Vec<DroppedPass>,
+    /// Сумма весов выбранных сеансов, чтобы сравнивать стратегии
+    pub total_weight: f64,
+}
+
+/// Отбрасывает слишком короткие пролеты, остальные возвращает кандидатами.
+/// Пролеты нулевой длительности отбрасываются всегда: в schedule_optimal такой кандидат
+/// мог бы оказаться в previous после самого себя, и восстановление плана зациклилось бы
+fn split_too_short(
+    passes: Vec<PassData>,
+    constraints: &ScheduleConstraints,
+    dropped: &mut Vec<DroppedPass>,
+) -> Vec<PassData> {
+    let mut candidates = vec![];
+
+    for pass_data in passes {
+        let duration = get_duration(&pass_data);
+
+        if duration > Duration::zero() && duration >= constraints.min_contact {
+            candidates.push(pass_data);
+            continue;
+        }
+
+        let duration_seconds = duration.num_milliseconds() as f64 / 1000.0;
+        let min_contact_seconds = constraints.min_contact.num_milliseconds() as f64 / 1000.0;
+
+        let explanation = if duration > Duration::zero() {
+            format!(
+                "Pass lasts {:.0} s, which is shorter than the minimum contact of {:.0} s",
+                duration_seconds, min_contact_seconds,
+            )
+        } else {
+            "Pass has no duration".to_string()
+        };
+
+        dropped.push(DroppedPass {
+            priority: constraints.priority(&pass_data),
+            explanation,
+            reason: DropReason::TooShort { duration_seconds, min_contact_seconds },
+            pass_data: pass_data.into(),
+        });
+    }
+
+    candidates
+}
+
+fn build_schedule(
+    candidates: Vec<PassData>,
+    selected: Vec<bool>,
+    constraints: &ScheduleConstraints,
+    mut dropped: Vec<DroppedPass>,
+    conflict_explanation: &str,
+) -> Schedule {
+    let selected_passes: Vec<&PassData> = candidates.iter()
+        .zip(&selected)
+        .filter_map(|(pass_data, &is_selected)| is_selected.then_some(pass_data))
+        .collect();
+
+    let total_weight = selected_passes.iter().map(|pass_data| constraints.weight(pass_data)).sum();
+
+    let reasons: Vec<Option<DropReason>> = candidates.iter()
+        .zip(&selected)
+        .map(|(pass_data, &is_selected)| {
+            if is_selected {
+                return None;
+            }
+
+            Some(match selected_passes.iter().find(|other| constraints.conflicts(pass_data, other)) {
+                Some(other) => DropReason::Conflict {
+                    conflicting_norad_id: other.norad_id.clone(),
+                    conflicting_rise_time: other.rise_time,
+                },
+                None => DropReason::NoGain,
+            })
+        })
+        .collect();
+
+    let mut contacts = vec![];
+
+    for (pass_data, reason) in candidates.into_iter().zip(reasons) {
+        let priority = constraints.priority(&pass_data);
+
+        match reason {
+            None => contacts.push(ScheduledContact { pass_data: pass_data.into(), priority }),
+            Some(reason) => {
+                let explanation = match &reason {
+                    DropReason::Conflict { conflicting_norad_id, conflicting_rise_time } => format!(
+                        "Overlaps, including {} s antenna slew, with the pass of {} rising at {}; {}",
+                        constraints.slew.num_seconds(), conflicting_norad_id.trim(), conflicting_rise_time,
+                        conflict_explanation,
+                    ),
+                    DropReason::NoGain => "Pass doesn't increase the total weight of the plan".to_string(),
+                    DropReason::TooShort { .. } => unreachable!("Too short passes are dropped before scheduling"),
+                };
+
+                dropped.push(DroppedPass { pass_data: pass_data.into(), priority, reason, explanation });
+            }
+        }
+    }
+
+    contacts.sort_by_key(|contact| contact.pass_data.rise_time);
+    dropped.sort_by_key(|dropped_pass| dropped_pass.pass_data.rise_time);
+
+    Schedule { contacts, dropped, total_weight }
+}
+
+/// Жадный план: пролеты берутся по убыванию приоритета, затем длительности, если не мешают уже выбранным
+pub fn schedule_greedy(passes: Vec<PassData>, constraints: &ScheduleConstraints) -> Schedule {
+    let mut dropped = vec![];
+    let candidates = split_too_short(passes, constraints, &mut dropped);
+
+    let mut order: Vec<usize> = (0..candidates.len()).collect();
+    order.sort_by(|&a, &b| {
+        let (a, b) = (&candidates[a], &candidates[b]);
+
+        constraints.priority(b).cmp(&constraints.priority(a))
+            .then(get_duration(b).cmp(&get_duration(a)))
+            .then(a.rise_time.cmp(&b.rise_time))
+    });
+
+    let mut selected = vec![false; candidates.len()];
+
+    for index in order {
+        let is_free = selected.iter()
+            .enumerate()
+            .filter(|&(_, &is_selected)| is_selected)
+            .all(|(other, _)| !constraints.conflicts(&candidates[index], &candidates[other]));
+
+        selected[index] = is_free;
+    }
+
+    build_schedule(candidates, selected, constraints, dropped, "that pass has higher priority or is longer")
+}
+
+/// Оптимальный план по сумме весов: взвешенная задача о выборе интервалов, решаемая динамическим программированием
+pub fn schedule_optimal(passes: Vec<PassData>, constraints: &ScheduleConstraints) -> Schedule {
+    let mut dropped = vec![];
+    let mut candidates = split_too_short(passes, constraints, &mut dropped);
+
+    candidates.sort_by_key(|pass_data| pass_data.fall_time);
+
+    // previous[j] - сколько первых кандидатов заканчиваются с учетом разворота до начала j-го
+    let previous: Vec<usize> = candidates.iter()
+        .map(|pass_data| {
+            candidates.partition_point(|other| other.fall_time + constraints.slew <= pass_data.rise_time)
+        })
+        .collect();
+
+    let mut best = vec![0.0; candidates.len() + 1];
+
+    for (index, pass_data) in candidates.iter().enumerate() {
+        best[index + 1] = f64::max(best[index], constraints.weight(pass_data) + best[previous[index]]);
+    }
+
+    let mut selected = vec![false; candidates.len()];
+    let mut index = candidates.len();
+
+    while index > 0 {
+        let with_pass = constraints.weight(&candidates[index - 1]) + best[previous[index - 1]];
+
+        if with_pass >= best[index - 1] && with_pass > 0.0 {
+            selected[index - 1] = true;
+            index = previous[index - 1];
+        } else {
+            index -= 1;
+        }
+    }
+
+    build_schedule(candidates, selected, constraints, dropped, "skipping this pass gives a higher total weight")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::calculations::{Illumination, ObserverLighting};
+
+    fn minutes(minutes: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap() + Duration::minutes(minutes)
+    }
+
+    fn pass(norad_id: &str, rise_minute: i64, fall_minute: i64) -> PassData {
+        PassData {
+            satellite_name: format!("SAT {}", norad_id),
+            norad_id: norad_id.to_string(),
+            rise_time: minutes(rise_minute),
+            rise_azimuth: 0.0,
+            apogee_time: minutes(rise_minute) + (minutes(fall_minute) - minutes(rise_minute)) / 2,
+            apogee_elevation: 45.0,
+            apogee_azimuth: 90.0,
+            fall_time: minutes(fall_minute),
+            fall_azimuth: 180.0,
+            illumination: Illumination::Sunlit,
+            observer_lighting: ObserverLighting::Darkness,
+            visible_intervals: vec![],
+            apogee_magnitude: None,
+        }
+    }
+
+    fn constraints(min_contact_seconds: i64, slew_seconds: i64) -> ScheduleConstraints {
+        ScheduleConstraints {
+            priorities: HashMap::new(),
+            min_contact: Duration::seconds(min_contact_seconds),
+            slew: Duration::seconds(slew_seconds),
+        }
+    }
+
+    fn contact_ids(schedule: &Schedule) -> Vec<&str> {
+        schedule.contacts.iter().map(|contact| contact.pass_data.norad_id.as_str()).collect()
+    }
+
+    fn dropped_ids(schedule: &Schedule) -> Vec<&str> {
+        schedule.dropped.iter().map(|dropped| dropped.pass_data.norad_id.as_str()).collect()
+    }
+
+    /// Длинный пролет перекрывает два коротких, которые вместе длиннее его
+    fn long_pass_between_two_short() -> Vec<PassData> {
+        vec![pass("1", 0, 35), pass("2", 10, 70), pass("3", 40, 80)]
+    }
+
+    #[test]
+    fn greedy_takes_the_longest_pass_first() {
+        let schedule = schedule_greedy(long_pass_between_two_short(), &constraints(0, 0));
+
+        assert_eq!(contact_ids(&schedule), ["2"]);
+        assert_eq!(schedule.total_weight, 60.0);
+        assert_eq!(dropped_ids(&schedule), ["1", "3"]);
+
+        for dropped in &schedule.dropped {
+            assert!(matches!(&dropped.reason, DropReason::Conflict { conflicting_norad_id, .. } if conflicting_norad_id == "2"));
+            assert!(dropped.explanation.ends_with("that pass has higher priority or is longer"), "{}", dropped.explanation);
+        }
+    }
+
+    #[test]
+    fn optimal_plan_beats_greedy_on_total_weight() {
+        let schedule = schedule_optimal(long_pass_between_two_short(), &constraints(0, 0));
+
+        assert_eq!(contact_ids(&schedule), ["1", "3"]);
+        assert_eq!(schedule.total_weight, 75.0);
+        assert_eq!(dropped_ids(&schedule), ["2"]);
+        assert!(matches!(&schedule.dropped[0].reason, DropReason::Conflict { conflicting_norad_id, .. } if conflicting_norad_id == "1"));
+        assert!(schedule.dropped[0].explanation.ends_with("skipping this pass gives a higher total weight"));
+    }
+
+    #[test]
+    fn priority_outweighs_duration() {
+        let mut constraints = constraints(0, 0);
+        constraints.priorities.insert("2".to_string(), 1);
+        constraints.priorities.insert("1".to_string(), 5);
+
+        let greedy = schedule_greedy(vec![pass("1", 0, 10), pass("2", 5, 30)], &constraints);
+        let optimal = schedule_optimal(vec![pass("1", 0, 10), pass("2", 5, 30)], &constraints);
+
+        assert_eq!(contact_ids(&greedy), ["1"]);
+        assert_eq!(contact_ids(&optimal), ["1"]);
+        assert_eq!(optimal.total_weight, 50.0);
+    }
+
+    #[test]
+    fn keeps_slew_gap_between_contacts() {
+        let passes = || vec![pass("1", 0, 10), pass("2", 11, 20)];
+
+        for schedule in [schedule_greedy(passes(), &constraints(0, 60)), schedule_optimal(passes(), &constraints(0, 60))] {
+            assert_eq!(contact_ids(&schedule), ["1", "2"]);
+        }
+
+        for schedule in [schedule_greedy(passes(), &constraints(0, 120)), schedule_optimal(passes(), &constraints(0, 120))] {
+            assert_eq!(contact_ids(&schedule), ["1"]);
+            assert!(schedule.dropped[0].explanation.starts_with("Overlaps, including 120 s antenna slew, with the pass of 1"));
+        }
+    }
+
+    #[test]
+    fn explains_too_short_passes() {
+        let schedule = schedule_optimal(vec![pass("1", 0, 3), pass("2", 10, 20)], &constraints(300, 0));
+
+        assert_eq!(contact_ids(&schedule), ["2"]);
+        assert!(matches!(
+            schedule.dropped[0].reason,
+            DropReason::TooShort { duration_seconds, min_contact_seconds } if duration_seconds == 180.0 && min_contact_seconds == 300.0
+        ));
+        assert_eq!(schedule.dropped[0].explanation, "Pass lasts 180 s, which is shorter than the minimum contact of 300 s");
+    }
+
+    #[test]
+    fn drops_zero_length_passes_with_default_constraints() {
+        let passes = || vec![pass("1", 0, 10), pass("2", 10, 10), pass("3", 10, 20)];
+
+        for schedule in [schedule_greedy(passes(), &constraints(0, 0)), schedule_optimal(passes(), &constraints(0, 0))] {
+            assert_eq!(contact_ids(&schedule), ["1", "3"]);
+            assert_eq!(dropped_ids(&schedule), ["2"]);
+            assert!(matches!(schedule.dropped[0].reason, DropReason::TooShort { .. }));
+            assert_eq!(schedule.dropped[0].explanation, "Pass has no duration");
+        }
+    }
+}
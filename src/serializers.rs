@@ -1,11 +1,12 @@
 use chrono::{DateTime, Utc};
 use serde::Serialize;
-use satellite::{Geodedic, Bearing};
-use super::calculations::PassData;
+use satellite::Geodedic;
+use super::calculations::{Illumination, LookSample, ObserverLighting, PassData, VisibleInterval};
 
 #[derive(Serialize)]
 pub struct SerializablePassData {
     pub satellite_name: String,
+    pub norad_id: String,
     pub rise_time: DateTime<Utc>,
     pub rise_azimuth: f64,
     pub fall_time: DateTime<Utc>,
@@ -13,12 +14,17 @@ pub struct SerializablePassData {
     pub apogee_time: DateTime<Utc>,
     pub apogee_elevation: f64,
     pub apogee_azimuth: f64,
+    pub illumination: Illumination,
+    pub observer_lighting: ObserverLighting,
+    pub visible_intervals: Vec<VisibleInterval>,
+    pub apogee_magnitude: Option<f64>,
 }
 
 impl From<PassData> for SerializablePassData {
     fn from(pass_data: PassData) -> Self {
         SerializablePassData {
             satellite_name: pass_data.satellite_name,
+            norad_id: pass_data.norad_id,
             rise_time: pass_data.rise_time,
             rise_azimuth: pass_data.rise_azimuth,
             fall_time: pass_data.fall_time,
@@ -26,6 +32,10 @@ impl From<PassData> for SerializablePassData {
             apogee_time: pass_data.apogee_time,
             apogee_elevation: pass_data.apogee_elevation,
             apogee_azimuth: pass_data.apogee_azimuth,
+            illumination: pass_data.illumination,
+            observer_lighting: pass_data.observer_lighting,
+            visible_intervals: pass_data.visible_intervals,
+            apogee_magnitude: pass_data.apogee_magnitude,
         }
     }
 }
@@ -51,13 +61,25 @@ impl From<Geodedic> for SerializableGeodedic {
 pub struct SerializableBearing {
     pub az: f64,
     pub el: f64,
+    pub range: f64,
+    pub range_rate: f64,
+    pub magnitude: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub downlink_hz: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uplink_hz: Option<f64>,
 }
 
-impl From<Bearing> for SerializableBearing {
-    fn from(bearing: Bearing) -> Self {
+impl From<LookSample> for SerializableBearing {
+    fn from(look_sample: LookSample) -> Self {
         SerializableBearing {
-            az: bearing.azimuth,
-            el: bearing.elevation,
+            az: look_sample.azimuth,
+            el: look_sample.elevation,
+            range: look_sample.range,
+            range_rate: look_sample.range_rate,
+            magnitude: look_sample.magnitude,
+            downlink_hz: None,
+            uplink_hz: None,
         }
     }
 }
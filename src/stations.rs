@@ -0,0 +1,200 @@
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum StationError {
+    #[error("Failed to read or write stations file")]
+    IoError(#[from] std::io::Error),
+    #[error("Stations file is malformed")]
+    InvalidStations(#[from] serde_json::Error),
+    #[error("Station id should contain only latin letters, digits, '-' and '_'")]
+    InvalidId,
+    #[error("Station {0:?} doesn't exist")]
+    StationNotFound(String),
+    #[error("Station {0:?} already exists")]
+    StationExists(String),
+    #[error("Either station or lat, lon and alt should be specified")]
+    MissingCoordinates,
+    #[error("Stations file couldn't be loaded, the registry is read-only until it is fixed")]
+    ReadOnly,
+}
+
+/// Сохраненная наземная станция
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Station {
+    pub id: String,
+    pub name: String,
+    pub lat: f64,
+    pub lon: f64,
+    /// Высота над уровнем моря в метрах
+    pub alt: f64,
+    pub min_elevation: f64,
+    /// Имя сохраненной маски горизонта
+    pub horizon_mask: Option<String>,
+    pub downlink_hz: Option<f64>,
+    pub uplink_hz: Option<f64>,
+}
+
+impl Station {
+    /// Наблюдатель, заданный координатами прямо в запросе
+    fn from_coordinates(lat: f64, lon: f64, alt: f64) -> Self {
+        Station {
+            id: String::new(),
+            name: String::new(),
+            lat,
+            lon,
+            alt,
+            min_elevation: 0.0,
+            horizon_mask: None,
+            downlink_hz: None,
+            uplink_hz: None,
+        }
+    }
+
+    pub fn observer(&self) -> satellite::Geodedic {
+        satellite::Geodedic {
+            latitude: self.lat * satellite::constants::DEG_2_RAD,
+            longitude: self.lon * satellite::constants::DEG_2_RAD,
+            height: self.alt / 1000.0,
+        }
+    }
+}
+
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 64 &&
+        id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Реестр станций, который целиком перезаписывается на диск после каждого изменения
+pub struct StationRegistry {
+    path: PathBuf,
+    stations: RwLock<Vec<Station>>,
+    /// Изменения идут по одному, чтобы запись файла не шла под блокировкой читателей
+    writing: tokio::sync::Mutex<()>,
+    /// Файл не удалось прочитать, перезаписывать его нельзя, иначе станции пропадут
+    read_only: bool,
+}
+
+impl StationRegistry {
+    /// Если файла еще нет, реестр начинается пустым
+    pub fn load(path: &str) -> Result<Self, StationError> {
+        let stations = match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(error) => return Err(error.into()),
+        };
+
+        Ok(StationRegistry::new(path, stations, false))
+    }
+
+    /// Пустой реестр, который отказывается менять поврежденный или недоступный файл
+    pub fn read_only(path: &str) -> Self {
+        StationRegistry::new(path, vec![], true)
+    }
+
+    fn new(path: &str, stations: Vec<Station>, read_only: bool) -> Self {
+        StationRegistry {
+            path: PathBuf::from(path),
+            stations: RwLock::new(stations),
+            writing: tokio::sync::Mutex::new(()),
+            read_only,
+        }
+    }
+
+    async fn write(&self, stations: &[Station]) -> Result<(), StationError> {
+        let tmp_path = self.path.with_extension("json.tmp");
+
+        tokio::fs::write(&tmp_path, serde_json::to_string_pretty(stations)?).await?;
+        tokio::fs::rename(&tmp_path, &self.path).await?;
+
+        Ok(())
+    }
+
+    /// Сохраняет измененный список на диск и только после этого показывает его читателям
+    async fn modify<T>(
+        &self,
+        change: impl FnOnce(&mut Vec<Station>) -> Result<T, StationError>,
+    ) -> Result<T, StationError> {
+        if self.read_only {
+            return Err(StationError::ReadOnly);
+        }
+
+        let _writing = self.writing.lock().await;
+
+        let mut updated = self.list();
+        let result = change(&mut updated)?;
+        self.write(&updated).await?;
+        *self.stations.write().expect("Stations lock shouldn't be poisoned") = updated;
+
+        Ok(result)
+    }
+
+    pub fn list(&self) -> Vec<Station> {
+        self.stations.read().expect("Stations lock shouldn't be poisoned").clone()
+    }
+
+    pub fn get(&self, id: &str) -> Result<Station, StationError> {
+        self.stations.read()
+            .expect("Stations lock shouldn't be poisoned")
+            .iter()
+            .find(|station| station.id == id)
+            .cloned()
+            .ok_or(StationError::StationNotFound(id.to_string()))
+    }
+
+    pub async fn create(&self, station: Station) -> Result<Station, StationError> {
+        if !is_valid_id(&station.id) {
+            return Err(StationError::InvalidId);
+        }
+
+        self.modify(|stations| {
+            if stations.iter().any(|existing| existing.id == station.id) {
+                return Err(StationError::StationExists(station.id.clone()));
+            }
+
+            stations.push(station.clone());
+
+            Ok(station)
+        }).await
+    }
+
+    pub async fn update(&self, station: Station) -> Result<Station, StationError> {
+        self.modify(|stations| {
+            let Some(index) = stations.iter().position(|existing| existing.id == station.id) else {
+                return Err(StationError::StationNotFound(station.id.clone()));
+            };
+
+            stations[index] = station.clone();
+
+            Ok(station)
+        }).await
+    }
+
+    pub async fn delete(&self, id: &str) -> Result<(), StationError> {
+        self.modify(|stations| {
+            if !stations.iter().any(|existing| existing.id == id) {
+                return Err(StationError::StationNotFound(id.to_string()));
+            }
+
+            stations.retain(|existing| existing.id != id);
+
+            Ok(())
+        }).await
+    }
+
+    /// Станция из реестра по id или наблюдатель из координат запроса
+    pub fn resolve(
+        &self,
+        station: Option<&str>,
+        lat: Option<f64>, lon: Option<f64>, alt: Option<f64>,
+    ) -> Result<Station, StationError> {
+        match (station, lat, lon, alt) {
+            (Some(id), _, _, _) => self.get(id),
+            (None, Some(lat), Some(lon), Some(alt)) => Ok(Station::from_coordinates(lat, lon, alt)),
+            _ => Err(StationError::MissingCoordinates),
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use chrono::{DateTime, Utc};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use super::calculations::{self, ElementSets};
+use super::catalog::normalize_norad_id;
+
+/// Набор элементов из трех строк tle
+struct TleRecord<'a> {
+    name: Option<&'a str>,
+    line1: &'a str,
+    line2: &'a str,
+}
+
+impl TleRecord<'_> {
+    fn norad_id(&self) -> Option<String> {
+        self.line1.get(2..7).map(normalize_norad_id)
+    }
+
+    /// Эпоха из колонок 19-32 первой строки
+    fn epoch(&self) -> Option<DateTime<Utc>> {
+        let epoch = self.line1.get(18..32)?;
+        let year = epoch.get(..2)?.parse().ok()?;
+        let days = epoch.get(2..)?.trim().parse().ok()?;
+
+        Some(calculations::get_epoch_from_tle(year, days))
+    }
+}
+
+fn parse_records(tle: &str) -> Vec<TleRecord<'_>> {
+    let lines: Vec<&str> = tle.lines().map(str::trim_end).collect();
+    let mut records = vec![];
+
+    for index in 0..lines.len().saturating_sub(1) {
+        let (line1, line2) = (lines[index], lines[index + 1]);
+
+        if line1.starts_with("1 ") && line2.starts_with("2 ") {
+            let name = index.checked_sub(1)
+                .map(|name_index| lines[name_index])
+                .filter(|name| !name.starts_with("1 ") && !name.starts_with("2 ") && !name.trim().is_empty());
+
+            records.push(TleRecord { name, line1, line2 });
+        }
+    }
+
+    records
+}
+
+/// Где в файле спутника лежит набор элементов с этой эпохой
+#[derive(Debug, Clone, Copy)]
+struct IndexedRecord {
+    epoch: DateTime<Utc>,
+    offset: u64,
+    length: usize,
+}
+
+/// Индекс файла истории; границы набора - от строки с именем (или первой строки) до конца второй строки
+fn index_records(tle: &str) -> Vec<IndexedRecord> {
+    let offset_of = |line: &str| line.as_ptr() as usize - tle.as_ptr() as usize;
+
+    let mut records: Vec<IndexedRecord> = parse_records(tle).iter()
+        .filter_map(|record| {
+            let start = offset_of(record.name.unwrap_or(record.line1));
+            let end = offset_of(record.line2) + record.line2.len();
+
+            Some(IndexedRecord { epoch: record.epoch()?, offset: start as u64, length: end - start })
+        })
+        .collect();
+
+    records.sort_by_key(|record| record.epoch);
+    records
+}
+
+/// Наборы, которые ближе всех по эпохе хотя бы к одному моменту окна: эпохи внутри окна
+/// и ближайшие эпохи до и после него
+fn select_for_window(records: &[IndexedRecord], start_time: DateTime<Utc>, end_time: DateTime<Utc>) -> &[IndexedRecord] {
+    let first = records.partition_point(|record| record.epoch < start_time).saturating_sub(1);
+    let last = usize::min(records.partition_point(|record| record.epoch <= end_time) + 1, records.len());
+
+    &records[first..last.max(first)]
+}
+
+/// История наборов элементов: по файлу на номер NORAD, в который новые эпохи только дописываются.
+/// Эпохи всех файлов держатся в памяти, сами наборы читаются с диска только для нужного окна
+pub struct TleHistory {
+    directory: PathBuf,
+    index: RwLock<HashMap<String, Vec<IndexedRecord>>>,
+}
+
+impl TleHistory {
+    /// Читает эпохи всех сохраненных наборов; если каталога еще нет, история начинается пустой
+    pub async fn load(directory: &str) -> Self {
+        let history = TleHistory { directory: PathBuf::from(directory), index: RwLock::new(HashMap::new()) };
+
+        if let Err(error) = history.load_index().await {
+            log::warn!("Failed to index tle history, older element sets won't be used: {:?}", error);
+        }
+
+        history
+    }
+
+    async fn load_index(&self) -> std::io::Result<()> {
+        let mut entries = match tokio::fs::read_dir(&self.directory).await {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(error),
+        };
+
+        let mut index = HashMap::new();
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let Some(norad_id) = path.file_stem().and_then(|stem| stem.to_str()).map(normalize_norad_id) else {
+                continue;
+            };
+
+            if path.extension().and_then(|extension| extension.to_str()) != Some("txt") {
+                continue;
+            }
+
+            index.insert(norad_id, index_records(&tokio::fs::read_to_string(&path).await?));
+        }
+
+        *self.index.write().expect("Tle history lock shouldn't be poisoned") = index;
+
+        Ok(())
+    }
+
+    fn path(&self, norad_id: &str) -> Option<PathBuf> {
+        let norad_id = normalize_norad_id(norad_id);
+
+        norad_id.chars().all(|c| c.is_ascii_alphanumeric())
+            .then(|| self.directory.join(format!("{}.txt", norad_id)))
+    }
+
+    fn has_epoch(&self, norad_id: &str, epoch: DateTime<Utc>) -> bool {
+        self.index.read()
+            .expect("Tle history lock shouldn't be poisoned")
+            .get(norad_id)
+            .is_some_and(|records| records.binary_search_by_key(&epoch, |record| record.epoch).is_ok())
+    }
+
+    /// Дописывает в историю наборы элементов с еще не сохраненными эпохами, возвращает их число
+    pub async fn append(&self, tle: &str) -> std::io::Result<usize> {
+        tokio::fs::create_dir_all(&self.directory).await?;
+
+        let mut appended = 0;
+
+        for record in parse_records(tle) {
+            let (Some(norad_id), Some(epoch)) = (record.norad_id(), record.epoch()) else { continue };
+            let Some(path) = self.path(&norad_id) else { continue };
+
+            if self.has_epoch(&norad_id, epoch) {
+                continue;
+            }
+
+            let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(&path).await?;
+            let offset = file.metadata().await?.len();
+            let name = record.name.unwrap_or(&norad_id);
+            let content = format!("{}\n{}\n{}\n", name, record.line1, record.line2);
+            file.write_all(content.as_bytes()).await?;
+
+            let indexed = IndexedRecord { epoch, offset, length: content.len() - 1 };
+            let mut index = self.index.write().expect("Tle history lock shouldn't be poisoned");
+            let records = index.entry(norad_id).or_default();
+            let position = records.partition_point(|existing| existing.epoch < epoch);
+            records.insert(position, indexed);
+
+            appended += 1;
+        }
+
+        Ok(appended)
+    }
+
+    async fn read_records(&self, norad_id: &str, records: &[IndexedRecord]) -> std::io::Result<Vec<satellite::io::Satrec>> {
+        let Some(path) = self.path(norad_id) else { return Ok(vec![]) };
+
+        let mut file = tokio::fs::File::open(path).await?;
+        let mut tle = String::new();
+
+        for record in records {
+            let mut buffer = vec![0; record.length];
+            file.seek(SeekFrom::Start(record.offset)).await?;
+            file.read_exact(&mut buffer).await?;
+
+            tle.push_str(&String::from_utf8_lossy(&buffer));
+            tle.push('\n');
+        }
+
+        Ok(satellite::io::parse_multiple(&tle).0)
+    }
+
+    /// Для каждого спутника наборы элементов из истории, которые могут оказаться ближе
+    /// по эпохе к какому-то моменту окна, чем текущий
+    pub async fn find_for_window(
+        &self,
+        satrecs: &[&satellite::io::Satrec],
+        start_time: DateTime<Utc>, end_time: DateTime<Utc>,
+    ) -> Vec<Vec<satellite::io::Satrec>> {
+        let mut result = vec![];
+
+        for satrec in satrecs {
+            let norad_id = normalize_norad_id(&satrec.satnum);
+            let current_epoch = calculations::get_epoch(satrec);
+
+            let selected: Vec<IndexedRecord> = {
+                let index = self.index.read().expect("Tle history lock shouldn't be poisoned");
+
+                index.get(&norad_id)
+                    .map(|records| select_for_window(records, start_time, end_time))
+                    .unwrap_or_default()
+                    .iter()
+                    .filter(|record| record.epoch != current_epoch)
+                    .copied()
+                    .collect()
+            };
+
+            if selected.is_empty() {
+                result.push(vec![]);
+                continue;
+            }
+
+            match self.read_records(&norad_id, &selected).await {
+                Ok(history) => result.push(history),
+                Err(error) => {
+                    log::warn!("Failed to read tle history of {}: {:?}", norad_id, error);
+                    result.push(vec![]);
+                }
+            }
+        }
+
+        result
+    }
+}
+
+/// Текущие наборы элементов вместе с найденными в истории
+pub fn merge_history<'a>(
+    satrecs: &[&'a satellite::io::Satrec],
+    history: &'a [Vec<satellite::io::Satrec>],
+) -> Vec<ElementSets<'a>> {
+    satrecs.iter()
+        .zip(history)
+        .map(|(&satrec, history)| ElementSets::new(satrec, history))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HISTORY: &str = "\
+ISS (ZARYA)
+1 25544U 98067A   24001.50000000  .00016717  00000-0  10270-3 0  9005
+2 25544  51.6416 247.4627 0006703 130.5360 325.0288 15.72125391 42364
+ISS (ZARYA)
+1 25544U 98067A   24003.50000000  .00016717  00000-0  10270-3 0  9007
+2 25544  51.6416 247.4627 0006703 130.5360 325.0288 15.72125391 42394
+1 25544U 98067A   24002.50000000  .00016717  00000-0  10270-3 0  9006
+2 25544  51.6416 247.4627 0006703 130.5360 325.0288 15.72125391 42380
+";
+
+    fn day(day_of_year: f64) -> DateTime<Utc> {
+        calculations::get_epoch_from_tle(24, day_of_year)
+    }
+
+    #[test]
+    fn indexes_records_by_epoch_with_their_byte_ranges() {
+        let records = index_records(HISTORY);
+
+        assert_eq!(records.iter().map(|record| record.epoch).collect::<Vec<_>>(), [day(1.5), day(2.5), day(3.5)]);
+
+        let second = &HISTORY[records[1].offset as usize..records[1].offset as usize + records[1].length];
+        assert!(second.starts_with("1 25544U 98067A   24002.5"));
+        assert!(second.ends_with("42380"));
+
+        let first = &HISTORY[records[0].offset as usize..records[0].offset as usize + records[0].length];
+        assert!(first.starts_with("ISS (ZARYA)\n1 25544U"));
+    }
+
+    #[test]
+    fn selects_epochs_inside_the_window_and_its_neighbours() {
+        let records = index_records(HISTORY);
+
+        let selected = select_for_window(&records, day(2.0), day(2.75));
+        assert_eq!(selected.iter().map(|record| record.epoch).collect::<Vec<_>>(), [day(1.5), day(2.5), day(3.5)]);
+
+        let selected = select_for_window(&records, day(10.0), day(11.0));
+        assert_eq!(selected.iter().map(|record| record.epoch).collect::<Vec<_>>(), [day(3.5)]);
+
+        let selected = select_for_window(&records, day(0.5), day(1.0));
+        assert_eq!(selected.iter().map(|record| record.epoch).collect::<Vec<_>>(), [day(1.5)]);
+    }
+}
@@ -0,0 +1,281 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::catalog::normalize_norad_id;
+
+/// Длина строки tle вместе с контрольной суммой
+const TLE_LINE_LENGTH: usize = 69;
+
+#[derive(Serialize, Debug, Clone, Error)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum TleRecordError {
+    #[error("Line {line_number} of the element set is {length} characters long instead of 69")]
+    InvalidLength { line_number: u8, length: usize },
+    #[error("Line {line_number} of the element set has checksum {found:?}, but {expected} was computed")]
+    ChecksumMismatch { line_number: u8, expected: u32, found: String },
+    #[error("NORAD id {line1:?} in line 1 doesn't match {line2:?} in line 2")]
+    NoradIdMismatch { line1: String, line2: String },
+    #[error("Line 2 of the element set starts with line number {found:?}")]
+    WrongLineNumber { found: String },
+    #[error("Line 1 isn't followed by line 2, the download may be truncated")]
+    MissingLine2,
+    #[error("Line 2 doesn't follow line 1")]
+    UnexpectedLine2,
+    #[error("Name line isn't followed by line 1")]
+    MissingLine1,
+}
+
+/// Ошибка одного набора элементов с номером строки во входном тексте (с единицы)
+#[derive(Serialize, Debug, Clone)]
+pub struct RecordError {
+    pub line: usize,
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub error: TleRecordError,
+    pub message: String,
+}
+
+/// Проверенный набор элементов, имя есть только у 3LE
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TleRecord {
+    pub name: Option<String>,
+    pub line1: String,
+    pub line2: String,
+}
+
+impl TleRecord {
+    pub fn norad_id(&self) -> String {
+        normalize_norad_id(&self.line1[2..7])
+    }
+
+    /// Имя спутника, а для 2LE - номер NORAD, чтобы набор можно было найти и отфильтровать
+    pub fn display_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.norad_id())
+    }
+
+    /// Набор элементов в виде 3LE, который понимают каталог и история
+    pub fn to_3le(&self) -> String {
+        format!("{}\n{}\n{}\n", self.display_name(), self.line1, self.line2)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ParsedTle {
+    pub records: Vec<TleRecord>,
+    pub errors: Vec<RecordError>,
+}
+
+/// Контрольная сумма строки tle: сумма цифр, где минус считается единицей, по модулю 10
+pub fn get_checksum(line: &str) -> u32 {
+    line.chars()
+        .take(TLE_LINE_LENGTH - 1)
+        .map(|c| match c {
+            '-' => 1,
+            c => c.to_digit(10).unwrap_or(0),
+        })
+        .sum::<u32>() % 10
+}
+
+/// Space-Track начинает строку с именем с "0 "
+fn parse_name(line: &str) -> String {
+    let line = line.trim();
+
+    line.strip_prefix("0 ").unwrap_or(line).trim().to_string()
+}
+
+fn validate_line(line: &str, line_number: u8) -> Result<(), TleRecordError> {
+    if line.len() != TLE_LINE_LENGTH || !line.is_ascii() {
+        return Err(TleRecordError::InvalidLength { line_number, length: line.chars().count() });
+    }
+
+    let expected = get_checksum(line);
+    let found = &line[TLE_LINE_LENGTH - 1..];
+
+    if found.parse::<u32>().ok() != Some(expected) {
+        return Err(TleRecordError::ChecksumMismatch { line_number, expected, found: found.to_string() });
+    }
+
+    Ok(())
+}
+
+fn validate_record(line1: &str, line2: &str) -> Result<(), TleRecordError> {
+    validate_line(line1, 1)?;
+    validate_line(line2, 2)?;
+
+    let (norad_id1, norad_id2) = (&line1[2..7], &line2[2..7]);
+
+    if norad_id1 != norad_id2 {
+        return Err(TleRecordError::NoradIdMismatch {
+            line1: norad_id1.to_string(),
+            line2: norad_id2.to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Строка полной длины с номером строки, который не может идти после строки 1
+/// (строки с именем от Space-Track начинаются с "0 ")
+fn has_wrong_line_number(line: &str) -> bool {
+    let bytes = line.as_bytes();
+
+    line.len() == TLE_LINE_LENGTH && bytes[0].is_ascii_digit() && bytes[1] == b' ' &&
+        !matches!(bytes[0], b'0' | b'1' | b'2')
+}
+
+/// Разбирает 2LE и 3LE вперемешку, пропуская пустые строки; ошибочный набор не сдвигает разбор следующих
+pub fn parse(tle: &str) -> ParsedTle {
+    let lines: Vec<(usize, &str)> = tle.lines()
+        .map(str::trim_end)
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| (index + 1, line))
+        .collect();
+
+    let mut parsed = ParsedTle::default();
+    let mut index = 0;
+
+    while index < lines.len() {
+        let (line_number, line) = lines[index];
+        let next_line = lines.get(index + 1).map(|&(_, line)| line);
+        let next_next_line = lines.get(index + 2).map(|&(_, line)| line);
+
+        let (name, line1, line2) = if line.starts_with("1 ") {
+            (None, line, next_line)
+        } else if line.starts_with("2 ") {
+            parsed.errors.push(record_error(line_number, None, TleRecordError::UnexpectedLine2));
+            index += 1;
+            continue;
+        } else if next_line.is_some_and(|next_line| next_line.starts_with("1 ")) {
+            (Some(parse_name(line)), next_line.unwrap_or_default(), next_next_line)
+        } else {
+            parsed.errors.push(record_error(line_number, Some(parse_name(line)), TleRecordError::MissingLine1));
+            index += 1;
+            continue;
+        };
+
+        let name_lines = usize::from(name.is_some());
+
+        if let Some(line2) = line2.filter(|line2| has_wrong_line_number(line2)) {
+            let found = line2[..1].to_string();
+            parsed.errors.push(record_error(line_number, name, TleRecordError::WrongLineNumber { found }));
+            index += 2 + name_lines;
+            continue;
+        }
+
+        let Some(line2) = line2.filter(|line2| line2.starts_with("2 ")) else {
+            parsed.errors.push(record_error(line_number, name, TleRecordError::MissingLine2));
+            index += 1 + name_lines;
+            continue;
+        };
+
+        match validate_record(line1, line2) {
+            Ok(()) => parsed.records.push(TleRecord { name, line1: line1.to_string(), line2: line2.to_string() }),
+            Err(error) => parsed.errors.push(record_error(line_number, name, error)),
+        }
+
+        index += 2 + name_lines;
+    }
+
+    parsed
+}
+
+fn record_error(line: usize, name: Option<String>, error: TleRecordError) -> RecordError {
+    RecordError { line, name, message: error.to_string(), error }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ISS_LINE1: &str = "1 25544U 98067A   08264.51782528 -.00002182  00000+0 -11606-4 0  2926";
+    const ISS_LINE2: &str = "2 25544  51.6416 247.4627 0006703 130.5360 325.0288 15.72125391563537";
+
+    /// Строка с пересчитанной контрольной суммой
+    fn with_checksum(line: &str) -> String {
+        format!("{}{}", &line[..TLE_LINE_LENGTH - 1], get_checksum(line))
+    }
+
+    /// Второй спутник с теми же элементами, чтобы проверять, что разбор не сдвигается
+    fn other_lines() -> (String, String) {
+        (with_checksum(&ISS_LINE1.replace("25544", "25545")), with_checksum(&ISS_LINE2.replace("25544", "25545")))
+    }
+
+    fn names(parsed: &ParsedTle) -> Vec<String> {
+        parsed.records.iter().map(TleRecord::display_name).collect()
+    }
+
+    fn errors(parsed: &ParsedTle) -> Vec<(usize, Option<String>, String)> {
+        parsed.errors.iter()
+            .map(|error| (error.line, error.name.clone(), format!("{:?}", error.error)))
+            .collect()
+    }
+
+    #[test]
+    fn skips_blank_lines_between_records() {
+        let (line1, line2) = other_lines();
+        let parsed = parse(&format!("ISS (ZARYA)\n{}\n{}\n\n  \nOTHER\n{}\n{}\n", ISS_LINE1, ISS_LINE2, line1, line2));
+
+        assert_eq!(names(&parsed), ["ISS (ZARYA)", "OTHER"]);
+        assert_eq!(parsed.records[1].line1, line1);
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    }
+
+    #[test]
+    fn parses_2le_without_name_lines() {
+        let (line1, line2) = other_lines();
+        let parsed = parse(&format!("{}\n{}\n{}\n{}\n", ISS_LINE1, ISS_LINE2, line1, line2));
+
+        assert_eq!(names(&parsed), ["25544", "25545"]);
+        assert!(parsed.records.iter().all(|record| record.name.is_none()));
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    }
+
+    #[test]
+    fn reports_truncated_last_record() {
+        let (line1, _) = other_lines();
+        let parsed = parse(&format!("ISS (ZARYA)\n{}\n{}\nOTHER\n{}\n", ISS_LINE1, ISS_LINE2, &line1[..40]));
+
+        assert_eq!(names(&parsed), ["ISS (ZARYA)"]);
+        assert_eq!(errors(&parsed), [(4, Some("OTHER".to_string()), "MissingLine2".to_string())]);
+    }
+
+    #[test]
+    fn reports_bad_checksum_without_shifting_next_record() {
+        let (line1, line2) = other_lines();
+        let broken_line2 = format!("{}0", &ISS_LINE2[..TLE_LINE_LENGTH - 1]);
+        let parsed = parse(&format!("ISS (ZARYA)\n{}\n{}\nOTHER\n{}\n{}\n", ISS_LINE1, broken_line2, line1, line2));
+
+        assert_eq!(names(&parsed), ["OTHER"]);
+        assert_eq!(errors(&parsed), [(
+            1, Some("ISS (ZARYA)".to_string()),
+            format!("{:?}", TleRecordError::ChecksumMismatch { line_number: 2, expected: 7, found: "0".to_string() }),
+        )]);
+    }
+
+    #[test]
+    fn reports_wrong_line_number_without_shifting_next_record() {
+        let (line1, line2) = other_lines();
+        let misnumbered = with_checksum(&format!("3{}", &ISS_LINE2[1..]));
+        let parsed = parse(&format!("ISS (ZARYA)\n{}\n{}\nOTHER\n{}\n{}\n", ISS_LINE1, misnumbered, line1, line2));
+
+        assert_eq!(names(&parsed), ["OTHER"]);
+        assert_eq!(errors(&parsed), [(
+            1, Some("ISS (ZARYA)".to_string()),
+            format!("{:?}", TleRecordError::WrongLineNumber { found: "3".to_string() }),
+        )]);
+    }
+
+    #[test]
+    fn reports_norad_id_mismatch_between_lines() {
+        let (_, line2) = other_lines();
+        let parsed = parse(&format!("ISS (ZARYA)\n{}\n{}\n", ISS_LINE1, line2));
+
+        assert!(parsed.records.is_empty());
+        assert_eq!(errors(&parsed), [(
+            1, Some("ISS (ZARYA)".to_string()),
+            format!("{:?}", TleRecordError::NoradIdMismatch { line1: "25544".to_string(), line2: "25545".to_string() }),
+        )]);
+        assert_eq!(parsed.errors[0].message, "NORAD id \"25544\" in line 1 doesn't match \"25545\" in line 2");
+    }
+}
@@ -1,10 +1,24 @@
-use actix_web::{HttpResponse, web};
+use std::collections::HashMap;
+
+use actix_web::{http::header, HttpRequest, HttpResponse, web};
 use validator::Validate;
-use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
 use serde::Serialize;
 
-use super::{calculations, fetch_tle};
-use super::forms::{PassesListForm, SatelliteDataForm, TrajectoryForm};
+use super::{calculations, coverage, czml, delimited, fetch_tle, footprint, ground_track, ics, rig, rotator, scheduler, tle_history};
+use super::delimited::Delimiter;
+use super::catalog::{normalize_norad_id, CatalogStore};
+use super::horizon_mask::{HorizonMask, HorizonMaskError, HorizonMaskStore};
+use super::stations::{Station, StationError, StationRegistry};
+use super::tle_history::TleHistory;
+use super::magnitudes::StandardMagnitudes;
+use super::forms::{
+    ContactScheduleForm, CzmlForm, DemHorizonMaskForm, FootprintForm, NetworkPassesForm, OutputFormat,
+    ScheduleStrategy, StationForm, PassesListForm, PassesSorting,
+    RigStartForm, RotatorStartForm, SatelliteDataForm, TrajectoryForm,
+};
+use super::rig::{RigController, RigError};
+use super::rotator::{RotatorController, RotatorError};
 use super::serializers::{SerializableGeodedic, SerializableBearing, SerializablePassData};
 
 macro_rules! unwrap_or_return_response {
@@ -17,32 +31,92 @@ macro_rules! unwrap_or_return_response {
     };
 }
 
-pub async fn get_satellites_list() -> HttpResponse {
-    let tle_fetching_settings = fetch_tle::read_settings().await;
+macro_rules! unwrap_or_return_lookup_error {
+    ($result:expr) => {
+        match $result {
+            Ok(data) => data,
+            Err(error) => return HttpResponse::BadRequest()
+                .json(serde_json::json!({"error": error.to_string()}))
+        }
+    };
+}
+
+/// Явно указанный параметр format важнее заголовка Accept
+fn negotiate_format(requested: Option<OutputFormat>, request: &HttpRequest) -> OutputFormat {
+    if let Some(format) = requested {
+        return format;
+    }
 
-    HttpResponse::Ok().json(tle_fetching_settings.satellites_to_track)
+    let accept = request.headers().get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    if accept.contains("text/csv") {
+        OutputFormat::Csv
+    } else if accept.contains("text/tab-separated-values") {
+        OutputFormat::Tsv
+    } else if accept.contains("text/calendar") {
+        OutputFormat::Ics
+    } else if accept.contains("application/geo+json") {
+        OutputFormat::Geojson
+    } else if accept.contains("application/vnd.google-earth.kml+xml") {
+        OutputFormat::Kml
+    } else {
+        OutputFormat::Json
+    }
+}
+
+fn unsupported_format_response() -> HttpResponse {
+    HttpResponse::NotAcceptable()
+        .json(serde_json::json!({"error": "This endpoint doesn't support the requested format"}))
 }
 
-pub async fn get_satellite_data(form: web::Query<SatelliteDataForm>) -> HttpResponse {
+/// Точки get_trajectory идут с шагом в секунду начиная с start_time
+fn to_track_points(
+    start_time: DateTime<Utc>, trajectory: &[SerializableGeodedic],
+) -> Vec<ground_track::TrackPoint> {
+    trajectory.iter().enumerate()
+        .map(|(shift, point)| ground_track::TrackPoint {
+            time: start_time + Duration::seconds(shift as i64),
+            lon: point.lon,
+            lat: point.lat,
+            alt: point.alt,
+        })
+        .collect()
+}
+
+/// Только спутники, для которых в каталоге есть набор элементов
+pub async fn get_satellites_list(catalog: web::Data<CatalogStore>) -> HttpResponse {
+    HttpResponse::Ok().json(catalog.snapshot().satellite_names())
+}
+
+pub async fn get_fetch_report(fetch_reports: web::Data<fetch_tle::FetchReportStore>) -> HttpResponse {
+    HttpResponse::Ok().json(fetch_reports.snapshot())
+}
+
+pub async fn get_satellite_data(
+    form: web::Query<SatelliteDataForm>,
+    catalog: web::Data<CatalogStore>, standard_magnitudes: web::Data<StandardMagnitudes>,
+    stations: web::Data<StationRegistry>,
+) -> HttpResponse {
     if let Err(error) = form.validate() {
         return HttpResponse::BadRequest().json(error);
     }
 
     let start_time = Utc::now();
 
-    let observer = satellite::Geodedic {
-        longitude: form.lon * satellite::constants::DEG_2_RAD,
-        latitude: form.lat * satellite::constants::DEG_2_RAD,
-        height: form.alt / 1000.0,
+    let station = match stations.resolve(form.station.as_deref(), form.lat, form.lon, form.alt) {
+        Ok(station) => station,
+        Err(error) => return station_error_response(error),
     };
+    let observer = station.observer();
 
-    let satrec = unwrap_or_return_response!(calculations::find_satrec(
-        &std::env::var("TLE_FILE_PATH").unwrap(),
-        &form.satellite_name,
-    ));
+    let catalog = catalog.snapshot();
+    let satrec = unwrap_or_return_lookup_error!(catalog.find_satrec(&form.satellite_name));
 
     let satellite_name = satrec.name.clone().unwrap_or("Unknown satellite".to_string());
     let norad_id = satrec.satnum.clone();
+    let international_designator = catalog.international_designator(satrec).map(str::to_string);
     let inclination = satrec.inclo * satellite::constants::RAD_TO_DEG;
     let eccentricity = satrec.ecco;
     let period_minutes = satellite::constants::TWO_PI / satrec.no;
@@ -51,28 +125,27 @@ pub async fn get_satellite_data(form: web::Query<SatelliteDataForm>) -> HttpResp
     let mean_anomaly = satrec.mo * satellite::constants::RAD_TO_DEG;
     let raan = satrec.nodeo * satellite::constants::RAD_TO_DEG;
 
-    let year = if satrec.epochyr < 57 { 2000 + satrec.epochyr } else { 1900 + satrec.epochyr };
-    let start_of_year_date = NaiveDate::from_ymd_opt(year as i32, 1, 1)
-        .expect("January 1st of year Satrec.epochyr should exist");
-    let start_of_year = NaiveDateTime::from(start_of_year_date);
-    let epoch_naive = start_of_year + Duration::seconds((satrec.epochdays * 86400.0) as i64);
-    let epoch = epoch_naive.and_utc();
+    let epoch = calculations::get_epoch(satrec);
 
     let is_geostationary = (period_minutes - 1436.0).abs() < 10.0;
+    let standard_magnitude = standard_magnitudes.get(satrec);
+    let refraction = form.refraction_params.to_refraction();
+
+    let element_sets = calculations::ElementSets::single(satrec);
 
     let trajectory = unwrap_or_return_response!(calculations::get_trajectory(
-        &satrec, start_time - Duration::hours(1), Duration::hours(2),
+        &element_sets, start_time - Duration::hours(1), Duration::hours(2),
     )).into_iter().map(Into::into).collect();
 
     let look_angles = unwrap_or_return_response!(calculations::get_observer_trajectory(
-        &satrec, start_time, Duration::hours(1), &observer,
+        &element_sets, start_time, Duration::hours(1), &observer, standard_magnitude, refraction,
     )).into_iter().map(Into::into).collect();
 
     let passes;
 
     if !is_geostationary {
         passes = unwrap_or_return_response!(calculations::get_satellite_passes(
-            &satrec, start_time, Duration::hours(24), &observer,
+            &element_sets, start_time, Duration::hours(24), &observer, standard_magnitude, refraction,
         )).into_iter().map(Into::into).collect();
     } else {
         passes = vec![];
@@ -82,6 +155,7 @@ pub async fn get_satellite_data(form: web::Query<SatelliteDataForm>) -> HttpResp
     struct SatelliteData {
         satellite_name: String,
         norad_id: String,
+        international_designator: Option<String>,
         inclination: f64,
         eccentricity: f64,
         period_minutes: f64,
@@ -99,6 +173,7 @@ pub async fn get_satellite_data(form: web::Query<SatelliteDataForm>) -> HttpResp
     let response = SatelliteData {
         satellite_name,
         norad_id,
+        international_designator,
         inclination,
         eccentricity,
         period_minutes,
@@ -116,25 +191,371 @@ pub async fn get_satellite_data(form: web::Query<SatelliteDataForm>) -> HttpResp
     HttpResponse::Ok().json(response)
 }
 
-pub async fn get_passes_list(form: web::Query<PassesListForm>) -> HttpResponse {
+pub async fn get_passes_list(
+    request: HttpRequest,
+    form: web::Query<PassesListForm>,
+    catalog: web::Data<CatalogStore>, standard_magnitudes: web::Data<StandardMagnitudes>,
+    horizon_masks: web::Data<HorizonMaskStore>, stations: web::Data<StationRegistry>,
+    tle_history: web::Data<TleHistory>,
+) -> HttpResponse {
     if let Err(error) = form.validate() {
         return HttpResponse::BadRequest().json(error);
     }
 
-    let tle_file_path = &std::env::var("TLE_FILE_PATH").unwrap();
+    let catalog = catalog.snapshot();
+
+    let satrecs = unwrap_or_return_lookup_error!(form.satellites.split(",")
+        .map(|satellite| catalog.find_satrec(satellite))
+        .collect::<Result<Vec<_>, _>>());
+
+    let station = match stations.resolve(form.station.as_deref(), form.lat, form.lon, form.alt) {
+        Ok(station) => station,
+        Err(error) => return station_error_response(error),
+    };
+    let observer = station.observer();
+
+    // Маска из запроса важнее маски станции
+    let horizon_mask_name = form.horizon_mask.as_deref().or(station.horizon_mask.as_deref());
+
+    let horizon_mask = match horizon_masks.load_optional(horizon_mask_name).await {
+        Ok(horizon_mask) => horizon_mask,
+        Err(error) => return horizon_mask_error_response(error),
+    };
+
+    let start_time = NaiveDateTime::parse_from_str(&form.start_time, "%Y-%m-%dT%H:%M")
+        .expect("Parsing shouldn't fail because datetime string was validated")
+        .and_utc();
+
+    let duration = Duration::hours(form.duration as i64);
+
+    let history = tle_history.find_for_window(&satrecs, start_time, start_time + duration).await;
+    let satellites = tle_history::merge_history(&satrecs, &history);
+
+    let mut passes = unwrap_or_return_response!(calculations::get_filtered_passes(
+        &satellites,
+        start_time, duration,
+        form.min_elevation.unwrap_or(station.min_elevation), form.min_apogee,
+        &observer,
+        &standard_magnitudes,
+        horizon_mask.as_ref(),
+        form.refraction_params.to_refraction(),
+    ));
+
+    if form.visible_only {
+        passes.retain(|pass_data| !pass_data.visible_intervals.is_empty());
+    }
+
+    if let Some(max_magnitude) = form.max_magnitude {
+        passes.retain(|pass_data| {
+            pass_data.apogee_magnitude.is_some_and(|magnitude| magnitude <= max_magnitude)
+        });
+    }
+
+    if form.sort_by == PassesSorting::Brightness {
+        // Пролеты с неизвестной звездной величиной оказываются в конце списка
+        passes.sort_by(|a, b| {
+            let a_magnitude = a.apogee_magnitude.unwrap_or(f64::INFINITY);
+            let b_magnitude = b.apogee_magnitude.unwrap_or(f64::INFINITY);
+            a_magnitude.total_cmp(&b_magnitude)
+        });
+    }
+
+    match negotiate_format(form.format, &request) {
+        OutputFormat::Json => HttpResponse::Ok().json(passes),
+        OutputFormat::Ics => HttpResponse::Ok()
+            .content_type("text/calendar; charset=utf-8")
+            .body(ics::render_passes(&passes, station.lat, station.lon, |pass_data| {
+                catalog.revolution_number(&pass_data.norad_id, pass_data.apogee_time, station.lat)
+            })),
+        OutputFormat::Csv => HttpResponse::Ok()
+            .content_type(Delimiter::Comma.content_type())
+            .body(delimited::render_passes(&passes, Delimiter::Comma)),
+        OutputFormat::Tsv => HttpResponse::Ok()
+            .content_type(Delimiter::Tab.content_type())
+            .body(delimited::render_passes(&passes, Delimiter::Tab)),
+        OutputFormat::Geojson | OutputFormat::Kml => unsupported_format_response(),
+    }
+}
+
+/// Пролеты над несколькими станциями сети и объединенное покрытие для каждого спутника
+pub async fn get_network_passes(
+    form: web::Query<NetworkPassesForm>,
+    catalog: web::Data<CatalogStore>, standard_magnitudes: web::Data<StandardMagnitudes>,
+    horizon_masks: web::Data<HorizonMaskStore>, stations: web::Data<StationRegistry>,
+    tle_history: web::Data<TleHistory>,
+) -> HttpResponse {
+    if let Err(error) = form.validate() {
+        return HttpResponse::BadRequest().json(error);
+    }
+
+    let catalog = catalog.snapshot();
+
+    let satrecs = unwrap_or_return_lookup_error!(form.satellites.split(",")
+        .map(|satellite| catalog.find_satrec(satellite))
+        .collect::<Result<Vec<_>, _>>());
+
+    let network = match form.stations.split(",")
+        .map(|id| stations.get(id.trim()))
+        .collect::<Result<Vec<_>, _>>() {
+        Ok(network) => network,
+        Err(error) => return station_error_response(error),
+    };
+
+    let start_time = NaiveDateTime::parse_from_str(&form.start_time, "%Y-%m-%dT%H:%M")
+        .expect("Parsing shouldn't fail because datetime string was validated")
+        .and_utc();
+
+    let duration = Duration::hours(form.duration as i64);
+
+    let history = tle_history.find_for_window(&satrecs, start_time, start_time + duration).await;
+    let satellites = tle_history::merge_history(&satrecs, &history);
+
+    let mut station_passes = vec![];
+
+    for station in &network {
+        let horizon_mask = match horizon_masks.load_optional(station.horizon_mask.as_deref()).await {
+            Ok(horizon_mask) => horizon_mask,
+            Err(error) => return horizon_mask_error_response(error),
+        };
+
+        let passes = unwrap_or_return_response!(calculations::get_filtered_passes(
+            &satellites,
+            start_time, duration,
+            station.min_elevation, form.min_apogee,
+            &station.observer(),
+            &standard_magnitudes,
+            horizon_mask.as_ref(),
+            None,
+        ));
+
+        station_passes.extend(passes.into_iter().map(|pass_data| (station.id.clone(), pass_data)));
+    }
+
+    station_passes.sort_by_key(|(_, pass_data)| pass_data.rise_time);
 
-    let satrecs: Vec<_> = form.satellites.split(",")
-        .map(|satellite| {
-            calculations::find_satrec(&tle_file_path, satellite)
-                .expect("Satrec should be found because satellites list was validated")
+    let coverage = coverage::get_network_coverage(&station_passes);
+
+    #[derive(Serialize)]
+    struct NetworkPassesData {
+        passes: Vec<coverage::StationPass>,
+        coverage: Vec<coverage::SatelliteCoverage>,
+    }
+
+    HttpResponse::Ok().json(NetworkPassesData {
+        passes: station_passes.into_iter()
+            .map(|(station_id, pass_data)| coverage::StationPass { station_id, pass_data: pass_data.into() })
+            .collect(),
+        coverage,
+    })
+}
+
+/// План сеансов связи одной антенны без пересечений; для каждого отброшенного пролета объясняется причина
+pub async fn get_contact_schedule(
+    form: web::Query<ContactScheduleForm>,
+    catalog: web::Data<CatalogStore>, standard_magnitudes: web::Data<StandardMagnitudes>,
+    horizon_masks: web::Data<HorizonMaskStore>, stations: web::Data<StationRegistry>,
+    tle_history: web::Data<TleHistory>,
+) -> HttpResponse {
+    if let Err(error) = form.validate() {
+        return HttpResponse::BadRequest().json(error);
+    }
+
+    let catalog = catalog.snapshot();
+
+    let satrecs = unwrap_or_return_lookup_error!(form.satellites.split(",")
+        .map(|satellite| catalog.find_satrec(satellite))
+        .collect::<Result<Vec<_>, _>>());
+
+    let mut priorities = HashMap::new();
+
+    for item in form.priorities.as_deref().unwrap_or_default().split(",").filter(|item| !item.trim().is_empty()) {
+        let Some((satellite, priority)) = item.rsplit_once(':') else {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({"error": format!("Priority {:?} should look like satellite:priority", item)}));
+        };
+
+        let Ok(priority) = priority.trim().parse::<u32>() else {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({"error": format!("Priority {:?} should be a non-negative integer", priority)}));
+        };
+
+        let satrec = unwrap_or_return_lookup_error!(catalog.find_satrec(satellite));
+        priorities.insert(normalize_norad_id(&satrec.satnum), priority);
+    }
+
+    let station = match stations.resolve(form.station.as_deref(), form.lat, form.lon, form.alt) {
+        Ok(station) => station,
+        Err(error) => return station_error_response(error),
+    };
+
+    let horizon_mask = match horizon_masks.load_optional(station.horizon_mask.as_deref()).await {
+        Ok(horizon_mask) => horizon_mask,
+        Err(error) => return horizon_mask_error_response(error),
+    };
+
+    let start_time = NaiveDateTime::parse_from_str(&form.start_time, "%Y-%m-%dT%H:%M")
+        .expect("Parsing shouldn't fail because datetime string was validated")
+        .and_utc();
+
+    let duration = Duration::hours(form.duration as i64);
+
+    let history = tle_history.find_for_window(&satrecs, start_time, start_time + duration).await;
+    let satellites = tle_history::merge_history(&satrecs, &history);
+
+    let passes = unwrap_or_return_response!(calculations::get_filtered_passes(
+        &satellites,
+        start_time, duration,
+        form.min_elevation.unwrap_or(station.min_elevation), 0.0,
+        &station.observer(),
+        &standard_magnitudes,
+        horizon_mask.as_ref(),
+        None,
+    ));
+
+    let constraints = scheduler::ScheduleConstraints {
+        priorities,
+        min_contact: Duration::seconds(form.min_contact_seconds as i64),
+        slew: Duration::seconds(form.slew_seconds as i64),
+    };
+
+    let schedule = match form.strategy {
+        ScheduleStrategy::Greedy => scheduler::schedule_greedy(passes, &constraints),
+        ScheduleStrategy::Optimal => scheduler::schedule_optimal(passes, &constraints),
+    };
+
+    HttpResponse::Ok().json(schedule)
+}
+
+pub async fn get_trajectory(
+    request: HttpRequest,
+    form: web::Query<TrajectoryForm>,
+    catalog: web::Data<CatalogStore>, standard_magnitudes: web::Data<StandardMagnitudes>,
+    stations: web::Data<StationRegistry>, tle_history: web::Data<TleHistory>,
+) -> HttpResponse {
+    if let Err(error) = form.validate() {
+        return HttpResponse::BadRequest().json(error);
+    }
+
+    let catalog = catalog.snapshot();
+    let satrec = unwrap_or_return_lookup_error!(catalog.find_satrec(&form.satellite));
+
+    let start_time = NaiveDateTime::parse_from_str(&form.start_time, "%Y-%m-%dT%H:%M")
+        .expect("Parsing shouldn't fail because datetime string was validated")
+        .and_utc();
+
+    let end_time = NaiveDateTime::parse_from_str(&form.end_time, "%Y-%m-%dT%H:%M")
+        .expect("Parsing shouldn't fail because datetime string was validated")
+        .and_utc();
+
+    let duration = end_time - start_time;
+
+    let history = tle_history.find_for_window(&[satrec], start_time, end_time).await;
+    let element_sets = calculations::ElementSets::new(satrec, &history[0]);
+
+    let station = match stations.resolve(form.station.as_deref(), form.lat, form.lon, form.alt) {
+        Ok(station) => station,
+        Err(error) => return station_error_response(error),
+    };
+    let observer = station.observer();
+
+    // Частоты из запроса важнее частот станции
+    let downlink_hz = form.downlink_hz.or(station.downlink_hz);
+    let uplink_hz = form.uplink_hz.or(station.uplink_hz);
+
+    let trajectory: Vec<SerializableGeodedic> = unwrap_or_return_response!(calculations::get_trajectory(
+        &element_sets, start_time, duration,
+    )).into_iter().map(Into::into).collect();
+
+    let look_angles: Vec<_> = unwrap_or_return_response!(calculations::get_observer_trajectory(
+        &element_sets, start_time, duration, &observer, standard_magnitudes.get(satrec),
+        form.refraction_params.to_refraction(),
+    )).into_iter()
+        .map(|look_sample| {
+            let mut bearing = SerializableBearing::from(look_sample);
+
+            bearing.downlink_hz = downlink_hz
+                .map(|frequency| calculations::get_downlink_frequency(frequency, bearing.range_rate));
+            bearing.uplink_hz = uplink_hz
+                .map(|frequency| calculations::get_uplink_frequency(frequency, bearing.range_rate));
+
+            bearing
         })
         .collect();
 
+    #[derive(Serialize)]
+    struct TrajectoryData {
+        trajectory: Vec<SerializableGeodedic>,
+        look_angles: Vec<SerializableBearing>,
+    }
+
+    let satellite_name = satrec.name.clone().unwrap_or("Unknown satellite".to_string());
+
+    let delimiter = match negotiate_format(form.format, &request) {
+        OutputFormat::Json => {
+            let response = TrajectoryData {
+                trajectory,
+                look_angles,
+            };
+
+            return HttpResponse::Ok().json(response);
+        }
+        OutputFormat::Csv => Delimiter::Comma,
+        OutputFormat::Tsv => Delimiter::Tab,
+        OutputFormat::Geojson => {
+            let track_points = to_track_points(start_time, &trajectory);
+            let geojson = ground_track::render_geojson(satellite_name.trim(), &satrec.satnum, &track_points);
+
+            return HttpResponse::Ok()
+                .content_type("application/geo+json")
+                .body(geojson.to_string());
+        }
+        OutputFormat::Kml => {
+            let track_points = to_track_points(start_time, &trajectory);
+
+            return HttpResponse::Ok()
+                .content_type("application/vnd.google-earth.kml+xml")
+                .body(ground_track::render_kml(satellite_name.trim(), &satrec.satnum, &track_points));
+        }
+        OutputFormat::Ics => return unsupported_format_response(),
+    };
+
+    HttpResponse::Ok()
+        .content_type(delimiter.content_type())
+        .body(delimited::render_trajectory(start_time, &trajectory, &look_angles, delimiter))
+}
+
+pub async fn get_czml(
+    form: web::Query<CzmlForm>,
+    catalog: web::Data<CatalogStore>, standard_magnitudes: web::Data<StandardMagnitudes>,
+    tle_history: web::Data<TleHistory>,
+) -> HttpResponse {
+    if let Err(error) = form.validate() {
+        return HttpResponse::BadRequest().json(error);
+    }
+
+    let catalog = catalog.snapshot();
+
+    let satrecs = unwrap_or_return_lookup_error!(form.satellites.split(",")
+        .map(|satellite| catalog.find_satrec(satellite))
+        .collect::<Result<Vec<_>, _>>());
+
     let start_time = NaiveDateTime::parse_from_str(&form.start_time, "%Y-%m-%dT%H:%M")
         .expect("Parsing shouldn't fail because datetime string was validated")
         .and_utc();
 
-    let duration = Duration::hours(form.duration as i64);
+    let end_time = NaiveDateTime::parse_from_str(&form.end_time, "%Y-%m-%dT%H:%M")
+        .expect("Parsing shouldn't fail because datetime string was validated")
+        .and_utc();
+
+    if end_time <= start_time || end_time - start_time > Duration::days(10) {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({"error": "Time window should be positive and not longer than 10 days"}));
+    }
+
+    let duration = end_time - start_time;
+
+    let history = tle_history.find_for_window(&satrecs, start_time, start_time + duration).await;
+    let satellites = tle_history::merge_history(&satrecs, &history);
 
     let observer = satellite::Geodedic {
         latitude: form.lat * satellite::constants::DEG_2_RAD,
@@ -143,34 +564,276 @@ pub async fn get_passes_list(form: web::Query<PassesListForm>) -> HttpResponse {
     };
 
     let passes = unwrap_or_return_response!(calculations::get_filtered_passes(
-        satrecs,
+        &satellites,
         start_time, duration,
-        form.min_elevation, form.min_apogee,
+        form.min_elevation, 0.0,
         &observer,
+        &standard_magnitudes,
+        None, form.refraction_params.to_refraction(),
     ));
 
-    HttpResponse::Ok().json(passes)
+    let step_seconds = form.step_seconds.unwrap_or(60) as usize;
+    let mut czml_satellites = vec![];
+
+    for element_sets in &satellites {
+        let trajectory = unwrap_or_return_response!(calculations::get_sampled_trajectory(
+            element_sets, start_time, duration, step_seconds,
+        ));
+        let satrec = element_sets.current();
+
+        czml_satellites.push(czml::CzmlSatellite {
+            norad_id: satrec.satnum.clone(),
+            name: satrec.name.clone().unwrap_or("Unknown satellite".to_string()).trim().to_string(),
+            trajectory,
+            passes: passes.iter().filter(|pass_data| pass_data.norad_id == satrec.satnum).collect(),
+        });
+    }
+
+    HttpResponse::Ok().json(czml::render_document(
+        start_time, end_time,
+        form.lat, form.lon, form.alt,
+        &czml_satellites,
+        step_seconds,
+    ))
 }
 
-pub async fn get_trajectory(form: web::Query<TrajectoryForm>) -> HttpResponse {
+pub async fn get_footprint(
+    form: web::Query<FootprintForm>,
+    catalog: web::Data<CatalogStore>, tle_history: web::Data<TleHistory>,
+) -> HttpResponse {
     if let Err(error) = form.validate() {
         return HttpResponse::BadRequest().json(error);
     }
 
-    let satrec = unwrap_or_return_response!(calculations::find_satrec(
-        &std::env::var("TLE_FILE_PATH").unwrap(),
-        &form.satellite,
-    ));
+    let catalog = catalog.snapshot();
+    let satrec = unwrap_or_return_lookup_error!(catalog.find_satrec(&form.satellite));
 
     let start_time = NaiveDateTime::parse_from_str(&form.start_time, "%Y-%m-%dT%H:%M")
         .expect("Parsing shouldn't fail because datetime string was validated")
         .and_utc();
 
-    let end_time = NaiveDateTime::parse_from_str(&form.end_time, "%Y-%m-%dT%H:%M")
-        .expect("Parsing shouldn't fail because datetime string was validated")
-        .and_utc();
+    let end_time = form.end_time.as_deref().map(|end_time| {
+        NaiveDateTime::parse_from_str(end_time, "%Y-%m-%dT%H:%M")
+            .expect("Parsing shouldn't fail because datetime string was validated")
+            .and_utc()
+    });
 
-    let duration = end_time - start_time;
+    if end_time.is_some_and(|end_time| end_time <= start_time || end_time - start_time > Duration::days(1)) {
+        return HttpResponse::BadRequest()
+            .json(serde_json::json!({"error": "Time window should be positive and not longer than 1 day"}));
+    }
+
+    let history = tle_history.find_for_window(&[satrec], start_time, end_time.unwrap_or(start_time)).await;
+    let element_sets = calculations::ElementSets::new(satrec, &history[0]);
+
+    let features: Vec<serde_json::Value> = match end_time {
+        Some(end_time) => {
+            let step_seconds = form.step_seconds.unwrap_or(60) as usize;
+
+            let trajectory = unwrap_or_return_response!(calculations::get_sampled_trajectory(
+                &element_sets, start_time, end_time - start_time, step_seconds,
+            ));
+
+            trajectory.iter()
+                .enumerate()
+                .map(|(index, position)| footprint::get_footprint_feature(
+                    start_time + Duration::seconds((index * step_seconds) as i64), position, form.min_elevation,
+                ))
+                .collect()
+        }
+        None => {
+            let position = unwrap_or_return_response!(calculations::get_satellite_pos(
+                element_sets.at(start_time), start_time,
+            ));
+            vec![footprint::get_footprint_feature(start_time, &position, form.min_elevation)]
+        }
+    };
+
+    HttpResponse::Ok()
+        .content_type("application/geo+json")
+        .body(serde_json::json!({"type": "FeatureCollection", "features": features}).to_string())
+}
+
+fn horizon_mask_error_response(error: HorizonMaskError) -> HttpResponse {
+    let body = serde_json::json!({"error": error.to_string()});
+
+    match &error {
+        HorizonMaskError::MaskNotFound(_) | HorizonMaskError::DemNotFound(_) => HttpResponse::NotFound().json(body),
+        HorizonMaskError::IoError(_) => {
+            log::warn!("Horizon mask storage failed: {:?}", error);
+            HttpResponse::InternalServerError().json(body)
+        }
+        _ => HttpResponse::BadRequest().json(body),
+    }
+}
+
+pub async fn get_horizon_mask(name: web::Path<String>, horizon_masks: web::Data<HorizonMaskStore>) -> HttpResponse {
+    match horizon_masks.load(&name).await {
+        Ok(horizon_mask) => HttpResponse::Ok().json(horizon_mask),
+        Err(error) => horizon_mask_error_response(error),
+    }
+}
+
+/// Принимает профиль маски в CSV (Content-Type: text/csv) или JSON
+pub async fn upload_horizon_mask(
+    request: HttpRequest,
+    name: web::Path<String>,
+    profile: String,
+    horizon_masks: web::Data<HorizonMaskStore>,
+) -> HttpResponse {
+    let is_csv = request.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("text/csv"));
+
+    let horizon_mask = if is_csv { HorizonMask::parse_csv(&profile) } else { HorizonMask::parse_json(&profile) };
+
+    let horizon_mask = match horizon_mask {
+        Ok(horizon_mask) => horizon_mask,
+        Err(error) => return horizon_mask_error_response(error),
+    };
+
+    match horizon_masks.save(&name, &horizon_mask).await {
+        Ok(()) => HttpResponse::Ok().json(horizon_mask),
+        Err(error) => horizon_mask_error_response(error),
+    }
+}
+
+pub async fn create_horizon_mask_from_dem(
+    name: web::Path<String>,
+    form: web::Form<DemHorizonMaskForm>,
+    horizon_masks: web::Data<HorizonMaskStore>,
+) -> HttpResponse {
+    if let Err(error) = form.validate() {
+        return HttpResponse::BadRequest().json(error);
+    }
+
+    let dem = match horizon_masks.read_dem(&form.dem).await {
+        Ok(dem) => dem,
+        Err(error) => return horizon_mask_error_response(error),
+    };
+
+    // Разбор карты высот и трассировка лучей занимают заметное время, поэтому не в потоке обработчика
+    let (lat, lon, alt) = (form.lat, form.lon, form.alt);
+    let horizon_mask = match web::block(move || HorizonMask::from_dem(&dem, lat, lon, alt)).await {
+        Ok(Ok(horizon_mask)) => horizon_mask,
+        Ok(Err(error)) => return horizon_mask_error_response(error),
+        Err(error) => {
+            log::warn!("Horizon mask calculation failed: {:?}", error);
+            return HttpResponse::InternalServerError()
+                .json(serde_json::json!({"error": "Horizon mask calculation failed"}));
+        }
+    };
+
+    match horizon_masks.save(&name, &horizon_mask).await {
+        Ok(()) => HttpResponse::Ok().json(horizon_mask),
+        Err(error) => horizon_mask_error_response(error),
+    }
+}
+
+fn station_error_response(error: StationError) -> HttpResponse {
+    let body = serde_json::json!({"error": error.to_string()});
+
+    match &error {
+        StationError::StationNotFound(_) => HttpResponse::NotFound().json(body),
+        StationError::StationExists(_) => HttpResponse::Conflict().json(body),
+        StationError::IoError(_) | StationError::InvalidStations(_) => {
+            log::warn!("Station registry storage failed: {:?}", error);
+            HttpResponse::InternalServerError().json(body)
+        }
+        StationError::ReadOnly => HttpResponse::ServiceUnavailable().json(body),
+        StationError::InvalidId | StationError::MissingCoordinates => HttpResponse::BadRequest().json(body),
+    }
+}
+
+fn to_station(id: String, form: StationForm) -> Station {
+    Station {
+        id,
+        name: form.name,
+        lat: form.lat,
+        lon: form.lon,
+        alt: form.alt,
+        min_elevation: form.min_elevation,
+        horizon_mask: form.horizon_mask,
+        downlink_hz: form.downlink_hz,
+        uplink_hz: form.uplink_hz,
+    }
+}
+
+pub async fn get_stations(stations: web::Data<StationRegistry>) -> HttpResponse {
+    HttpResponse::Ok().json(stations.list())
+}
+
+pub async fn get_station(id: web::Path<String>, stations: web::Data<StationRegistry>) -> HttpResponse {
+    match stations.get(&id) {
+        Ok(station) => HttpResponse::Ok().json(station),
+        Err(error) => station_error_response(error),
+    }
+}
+
+pub async fn create_station(
+    id: web::Path<String>,
+    form: web::Form<StationForm>,
+    stations: web::Data<StationRegistry>,
+) -> HttpResponse {
+    if let Err(error) = form.validate() {
+        return HttpResponse::BadRequest().json(error);
+    }
+
+    match stations.create(to_station(id.into_inner(), form.into_inner())).await {
+        Ok(station) => HttpResponse::Created().json(station),
+        Err(error) => station_error_response(error),
+    }
+}
+
+pub async fn update_station(
+    id: web::Path<String>,
+    form: web::Form<StationForm>,
+    stations: web::Data<StationRegistry>,
+) -> HttpResponse {
+    if let Err(error) = form.validate() {
+        return HttpResponse::BadRequest().json(error);
+    }
+
+    match stations.update(to_station(id.into_inner(), form.into_inner())).await {
+        Ok(station) => HttpResponse::Ok().json(station),
+        Err(error) => station_error_response(error),
+    }
+}
+
+pub async fn delete_station(id: web::Path<String>, stations: web::Data<StationRegistry>) -> HttpResponse {
+    match stations.delete(&id).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(error) => station_error_response(error),
+    }
+}
+
+/// Настройки лежат на сервере, поэтому их ошибка - не ошибка запроса
+fn rotator_error_response(error: RotatorError) -> HttpResponse {
+    log::warn!("Failed to load rotator settings: {:?}", error);
+
+    HttpResponse::InternalServerError().json(serde_json::json!({"error": error.to_string()}))
+}
+
+pub async fn start_rotator(
+    form: web::Form<RotatorStartForm>,
+    catalog: web::Data<CatalogStore>, rotator_controller: web::Data<RotatorController>,
+) -> HttpResponse {
+    if let Err(error) = form.validate() {
+        return HttpResponse::BadRequest().json(error);
+    }
+
+    let (norad_id, satellite_name) = {
+        let catalog = catalog.snapshot();
+        let satrec = unwrap_or_return_lookup_error!(catalog.find_satrec(&form.satellite));
+
+        (satrec.satnum.clone(), satrec.name.clone().unwrap_or("Unknown satellite".to_string()))
+    };
+
+    let settings = match rotator::read_settings().await {
+        Ok(settings) => settings,
+        Err(error) => return rotator_error_response(error),
+    };
 
     let observer = satellite::Geodedic {
         latitude: form.lat * satellite::constants::DEG_2_RAD,
@@ -178,25 +841,75 @@ pub async fn get_trajectory(form: web::Query<TrajectoryForm>) -> HttpResponse {
         height: form.alt / 1000.0,
     };
 
-    let trajectory = unwrap_or_return_response!(calculations::get_trajectory(
-        &satrec, start_time, duration,
-    )).into_iter().map(Into::into).collect();
+    rotator_controller.start(catalog, norad_id, satellite_name, observer, settings).await;
 
-    let look_angles = unwrap_or_return_response!(calculations::get_observer_trajectory(
-        &satrec, start_time, duration, &observer,
-    )).into_iter().map(Into::into).collect();
+    HttpResponse::Ok().json(rotator_controller.status())
+}
 
-    #[derive(Serialize)]
-    struct TrajectoryData {
-        trajectory: Vec<SerializableGeodedic>,
-        look_angles: Vec<SerializableBearing>,
+pub async fn stop_rotator(rotator_controller: web::Data<RotatorController>) -> HttpResponse {
+    rotator_controller.stop().await;
+
+    HttpResponse::Ok().json(rotator_controller.status())
+}
+
+pub async fn get_rotator_status(rotator_controller: web::Data<RotatorController>) -> HttpResponse {
+    HttpResponse::Ok().json(rotator_controller.status())
+}
+
+fn rig_error_response(error: RigError) -> HttpResponse {
+    let body = serde_json::json!({"error": error.to_string()});
+
+    match &error {
+        RigError::TransponderNotFound(_) => HttpResponse::BadRequest().json(body),
+        RigError::SettingsLoadingFailed(_) | RigError::InvalidSettings(_) => {
+            log::warn!("Failed to load rig settings: {:?}", error);
+            HttpResponse::InternalServerError().json(body)
+        }
     }
+}
 
-    let response = TrajectoryData {
-        trajectory,
-        look_angles,
+pub async fn start_rig(
+    form: web::Form<RigStartForm>,
+    catalog: web::Data<CatalogStore>, rig_controller: web::Data<RigController>,
+) -> HttpResponse {
+    if let Err(error) = form.validate() {
+        return HttpResponse::BadRequest().json(error);
+    }
+
+    let (norad_id, satellite_name) = {
+        let catalog = catalog.snapshot();
+        let satrec = unwrap_or_return_lookup_error!(catalog.find_satrec(&form.satellite));
+
+        (satrec.satnum.clone(), satrec.name.clone().unwrap_or("Unknown satellite".to_string()))
     };
 
-    HttpResponse::Ok().json(response)
+    let settings = match rig::read_settings().await {
+        Ok(settings) => settings,
+        Err(error) => return rig_error_response(error),
+    };
+
+    let transponder = match settings.find_transponder(&norad_id, &form.transponder) {
+        Ok(transponder) => transponder,
+        Err(error) => return rig_error_response(error),
+    };
+
+    let observer = satellite::Geodedic {
+        latitude: form.lat * satellite::constants::DEG_2_RAD,
+        longitude: form.lon * satellite::constants::DEG_2_RAD,
+        height: form.alt / 1000.0,
+    };
+
+    rig_controller.start(catalog, norad_id, satellite_name, observer, settings, transponder).await;
+
+    HttpResponse::Ok().json(rig_controller.status())
 }
 
+pub async fn stop_rig(rig_controller: web::Data<RigController>) -> HttpResponse {
+    rig_controller.stop().await;
+
+    HttpResponse::Ok().json(rig_controller.status())
+}
+
+pub async fn get_rig_status(rig_controller: web::Data<RigController>) -> HttpResponse {
+    HttpResponse::Ok().json(rig_controller.status())
+}